@@ -0,0 +1,135 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use ethabi::Uint;
+use hex_literal::hex;
+use std::collections::HashMap;
+use tornado_cash_heuristics::analysis::analyze::get_address_matches;
+use tornado_cash_heuristics::{Deposit, Withdraw};
+use web3::types::{H160, H256};
+
+// the 0.1 ETH pool on mainnet
+const POOL: [u8; 20] = hex!("12D66f87A04A9E220743712cE6d9bB1B5616B8Fc");
+
+// xorshift, so the dataset is the same in every run without depending on a RNG crate
+fn next(state: &mut u64) -> u64 {
+    *state ^= *state << 13;
+    *state ^= *state >> 7;
+    *state ^= *state << 17;
+    *state
+}
+
+// n deposits by n / 2 addresses and n withdraws, a tenth of which go to depositing addresses
+fn dataset(n: usize) -> (Vec<Deposit>, Vec<Withdraw>) {
+    let mut state = 0x2545f4914f6cdd1d;
+    let address = |i: u64| H160::from_low_u64_be(i + 1);
+
+    let deposits = (0..n)
+        .map(|i| {
+            Deposit::new(
+                1,
+                H256::from_low_u64_be(i as u64),
+                i as u128,
+                H160(POOL),
+                address(next(&mut state) % (n as u64 / 2)),
+            )
+        })
+        .collect();
+    let withdraws = (0..n)
+        .map(|i| {
+            let r = next(&mut state);
+            let receiver = if r.is_multiple_of(10) {
+                address(r / 10 % (n as u64 / 2))
+            } else {
+                address(n as u64 + i as u64)
+            };
+
+            Withdraw::new(
+                1,
+                H256::from_low_u64_be((n + i) as u64),
+                (next(&mut state) % n as u64) as u128,
+                H160(POOL),
+                receiver,
+                H160::zero(),
+                Uint::zero(),
+            )
+        })
+        .collect();
+
+    (deposits, withdraws)
+}
+
+// the previous implementation, which rescans all deposits and withdraws for each depositor
+fn get_address_matches_quadratic<'a>(
+    deposits: &[&'a Deposit],
+    withdraws: &[&'a Withdraw],
+) -> HashMap<H160, (Vec<&'a Deposit>, Vec<&'a Withdraw>)> {
+    deposits
+        .iter()
+        .fold(vec![], |mut addrs, d| {
+            if !addrs.contains(&d.from) {
+                addrs.push(d.from);
+            }
+            addrs
+        })
+        .into_iter()
+        .map(|a| {
+            let ds: Vec<&Deposit> = deposits.iter().filter(|d| d.from == a).copied().collect();
+            let earliest = ds.iter().map(|d| d.block_number).min().unwrap_or(0);
+            let ws: Vec<&Withdraw> = withdraws
+                .iter()
+                .filter(|w| w.receiver == a && w.block_number > earliest)
+                .copied()
+                .collect();
+            (a, (ds, ws))
+        })
+        .filter(|(_, (_, ws))| !ws.is_empty())
+        .collect()
+}
+
+// transaction hashes of the matches, to compare the results of both implementations
+fn hashes(
+    matches: &HashMap<H160, (Vec<&Deposit>, Vec<&Withdraw>)>,
+) -> HashMap<H160, (Vec<H256>, Vec<H256>)> {
+    matches
+        .iter()
+        .map(|(a, (ds, ws))| {
+            (
+                *a,
+                (
+                    ds.iter().map(|d| d.transaction_hash).collect(),
+                    ws.iter().map(|w| w.transaction_hash).collect(),
+                ),
+            )
+        })
+        .collect()
+}
+
+fn bench_address_matches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get_address_matches");
+    group.sample_size(10);
+
+    for n in [10_000, 1_000_000, 2_000_000] {
+        let (deposits, withdraws) = dataset(n);
+        let deposits: Vec<&Deposit> = deposits.iter().collect();
+        let withdraws: Vec<&Withdraw> = withdraws.iter().collect();
+
+        group.bench_with_input(BenchmarkId::new("hash_maps", n), &n, |b, _| {
+            b.iter(|| get_address_matches(&deposits, &withdraws))
+        });
+
+        // the quadratic implementation takes hours for millions of transactions
+        if n <= 10_000 {
+            assert_eq!(
+                hashes(&get_address_matches(&deposits, &withdraws)),
+                hashes(&get_address_matches_quadratic(&deposits, &withdraws))
+            );
+            group.bench_with_input(BenchmarkId::new("quadratic", n), &n, |b, _| {
+                b.iter(|| get_address_matches_quadratic(&deposits, &withdraws))
+            });
+        }
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_address_matches);
+criterion_main!(benches);
@@ -1,43 +1,31 @@
-use super::DepositWithdrawPattern;
-use crate::data::{Deposit, Withdraw};
-use crate::helpers::collect_pools;
-use crate::immut_append;
+use super::{
+    AddressLink, AnonymitySet, DepositWithdrawPattern, FeeDistribution, PartialPatternMatch,
+    PatternMatch, RelayerStats, Unit,
+};
+use crate::data::registry::registry;
+use crate::data::{Deposit, ESNormalTransaction, InBlock, InPool, Pool, Withdraw};
+use crate::helpers::pair_pools;
 use itertools::Itertools;
-use std::collections::HashMap;
-use web3::types::H160;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use web3::types::{H160, H256};
 
 // This function is not strictly needed - it is used in the deposit/withdraw pattern
 // finding function (match_pattern) to make the algorithm faster by pre-filtering
 // patterns.
-fn pattern_is_interesting(pattern: &DepositWithdrawPattern) -> bool {
-    // address has deposited more than once to the same pool...
-    (pattern.n0_1ETH > 1 || pattern.n1ETH > 1 || pattern.n10ETH > 1 || pattern.n100ETH > 1)
-    // ...and address has deposited to multiple different pools
-        && [
-            pattern.n0_1ETH,
-            pattern.n1ETH,
-            pattern.n10ETH,
-            pattern.n100ETH,
-        ]
-        .into_iter()
-        .filter(|e| *e > 0)
-        .count()
-            > 1
+fn pattern_is_interesting(
+    pattern: &DepositWithdrawPattern,
+    min_repeat: u64,
+    min_pools: usize,
+) -> bool {
+    // address has deposited at least min_repeat times to the same pool...
+    pattern.counts.values().any(|n| *n >= min_repeat)
+    // ...and address has deposited to at least min_pools different pools
+        && pattern.counts.values().filter(|n| **n > 0).count() >= min_pools
 }
 
 // check if each deposit to a pool was made before a withdraw from this pool
 fn earlier(deposits: &[&Deposit], withdraws: &[&Withdraw]) -> bool {
-    let (d0_1eth, d1eth, d10eth, d100eth) = collect_pools(deposits);
-    let (w0_1eth, w1eth, w10eth, w100eth) = collect_pools(withdraws);
-
-    [
-        (d0_1eth, w0_1eth),
-        (d1eth, w1eth),
-        (d10eth, w10eth),
-        (d100eth, w100eth),
-    ]
-    .iter()
-    .all(|(ds, ws)| {
+    pair_pools(deposits, withdraws).values().all(|(ds, ws)| {
         ds.iter()
             .zip(ws.iter())
             .all(|(d, w)| d.block_number <= w.block_number)
@@ -60,43 +48,193 @@ pub fn get_address_matches<'a>(
     deposits: &[&'a Deposit],
     withdraws: &[&'a Withdraw],
 ) -> HashMap<H160, (Vec<&'a Deposit>, Vec<&'a Withdraw>)> {
+    // index the deposits by depositor and remember each depositor's earliest deposit
+    let mut deposits_by_sender: HashMap<H160, (u128, Vec<&'a Deposit>)> = HashMap::new();
+    deposits.iter().for_each(|d| {
+        let (earliest, ds) = deposits_by_sender
+            .entry(d.from)
+            .or_insert((d.block_number, vec![]));
+        *earliest = d.block_number.min(*earliest);
+        ds.push(d);
+    });
+
+    // index the withdraws later than the earliest deposit of their receiver
+    let mut withdraws_by_receiver: HashMap<H160, Vec<&'a Withdraw>> = HashMap::new();
+    withdraws
+        .iter()
+        .filter(|w| {
+            deposits_by_sender
+                .get(&w.receiver)
+                .is_some_and(|(earliest, _)| w.block_number > *earliest)
+        })
+        .for_each(|w| withdraws_by_receiver.entry(w.receiver).or_default().push(w));
+
+    // yield only the depositors for which there are any such withdraws
+    withdraws_by_receiver
+        .into_iter()
+        .map(|(a, ws)| (a, (deposits_by_sender.remove(&a).unwrap().1, ws)))
+        .collect()
+}
+
+/// Returns a HashMap that assigns to each address that deposited on one chain and withdrew on
+/// another chain a tuple consisting of a vector of its deposits and a vector of its withdraws.
+/// Only deposits for which there is a withdraw on another chain (and vice versa) are contained.
+///
+/// Since block numbers of different chains can not be compared, the order of deposits and
+/// withdraws is not taken into account.
+///
+/// # Arguments
+///
+/// * `deposits` - a slice of references to Deposit structures to scan
+/// * `withdraws` - a slice of references to Withdraw structures to scan
+pub fn get_cross_chain_address_matches<'a>(
+    deposits: &[&'a Deposit],
+    withdraws: &[&'a Withdraw],
+) -> HashMap<H160, (Vec<&'a Deposit>, Vec<&'a Withdraw>)> {
+    let mut withdraws_by_receiver: HashMap<H160, Vec<&'a Withdraw>> = HashMap::new();
+    withdraws
+        .iter()
+        .for_each(|w| withdraws_by_receiver.entry(w.receiver).or_default().push(w));
+
+    let mut deposits_by_sender: HashMap<H160, Vec<&'a Deposit>> = HashMap::new();
     deposits
         .iter()
-        // get all depositors without duplicates
-        .fold(vec![], |addrs, d| {
-            if !addrs.contains(&d.from) {
-                immut_append!(addrs, d.from)
+        .filter(|d| withdraws_by_receiver.contains_key(&d.from))
+        .for_each(|d| deposits_by_sender.entry(d.from).or_default().push(d));
+
+    deposits_by_sender
+        .into_iter()
+        .filter_map(|(a, ds)| {
+            let ws = &withdraws_by_receiver[&a];
+
+            // keep only transactions with a counterpart on another chain
+            let cross_ds: Vec<&Deposit> = ds
+                .iter()
+                .filter(|d| ws.iter().any(|w| w.chain != d.chain))
+                .copied()
+                .collect();
+            let cross_ws: Vec<&Withdraw> = ws
+                .iter()
+                .filter(|w| ds.iter().any(|d| d.chain != w.chain))
+                .copied()
+                .collect();
+
+            if !cross_ds.is_empty() {
+                Some((a, (cross_ds, cross_ws)))
             } else {
-                addrs
+                None
             }
         })
-        .into_iter()
-        // get all deposits and withdraws for each of the depositors
-        .map(|a| {
+        .collect()
+}
+
+/// Returns pairs of a deposit and a later withdraw (on the same chain) that were sent with the
+/// same unusual gas price. A gas price is unusual if it is not a multiple of `round` (all gas
+/// prices are unusual if `round` is 0) and if it was used by at most `max_uses` deposits and at
+/// most `max_uses` withdraws. Deposits and withdraws with unknown gas price are ignored. The pairs
+/// are sorted by chain, block numbers and transaction hashes.
+///
+/// # Arguments
+///
+/// * `deposits` - a slice of references to Deposit structures to scan
+/// * `withdraws` - a slice of references to Withdraw structures to scan
+/// * `round` - gas prices that are a multiple of this value (in wei) are common
+/// * `max_uses` - maximum number of deposits (and withdraws) with the same gas price
+pub fn get_gas_price_matches<'a>(
+    deposits: &[&'a Deposit],
+    withdraws: &[&'a Withdraw],
+    round: u128,
+    max_uses: usize,
+) -> Vec<(&'a Deposit, &'a Withdraw)> {
+    let unusual = |g: u128| !g.is_multiple_of(round);
+
+    let mut deposits_by_price: HashMap<(u64, u128), Vec<&'a Deposit>> = HashMap::new();
+    deposits
+        .iter()
+        .filter_map(|d| Some(((d.chain, d.gas_price?), *d)))
+        .filter(|((_, g), _)| unusual(*g))
+        .for_each(|(k, d)| deposits_by_price.entry(k).or_default().push(d));
+
+    let mut withdraws_by_price: HashMap<(u64, u128), Vec<&'a Withdraw>> = HashMap::new();
+    withdraws
+        .iter()
+        .filter_map(|w| Some(((w.chain, w.gas_price?), *w)))
+        .filter(|(k, _)| deposits_by_price.contains_key(k))
+        .for_each(|(k, w)| withdraws_by_price.entry(k).or_default().push(w));
+
+    withdraws_by_price
+        .iter()
+        .filter(|(k, ws)| ws.len() <= max_uses && deposits_by_price[*k].len() <= max_uses)
+        .flat_map(|(k, ws)| {
+            deposits_by_price[k].iter().flat_map(move |d| {
+                ws.iter()
+                    .filter(move |w| d.block_number < w.block_number)
+                    .map(move |w| (*d, *w))
+            })
+        })
+        .sorted_by_key(|(d, w)| {
             (
-                a,
-                (
-                    deposits.iter().filter(|d| d.from == a).copied().collect(),
-                    withdraws
-                        .iter()
-                        .filter(|w| w.receiver == a)
-                        .copied()
-                        .collect(),
-                ),
+                d.chain,
+                d.block_number,
+                w.block_number,
+                d.transaction_hash,
+                w.transaction_hash,
             )
         })
-        // create tuples that pair each address with two vectors of deposits and withdraws after the
-        // earliest deposits, yield only those for which there are any such withdraws
-        .filter_map(|(a, (ds, ws)): (H160, (Vec<&Deposit>, Vec<&Withdraw>))| {
-            // get all withdraws later than the earliest deposit
-            let later_ws: Vec<&Withdraw> = ws
-                .into_iter()
-                .filter(|w| w.block_number > ds.iter().map(|d| d.block_number).min().unwrap_or(0))
-                .collect();
+        .collect()
+}
 
-            // check if any such withdraws were found, if yes, yield the match
-            if !ds.is_empty() && !later_ws.is_empty() {
-                Some((a, (ds, later_ws)))
+/// Returns the withdraws for which the deposits that could plausibly have funded them were made
+/// by at most `max_addresses` addresses, together with these candidate deposits. A deposit is a
+/// candidate for a withdraw if it was made at most `window` blocks or seconds (see `unit`) before
+/// the withdraw and was not already found to be the only candidate of an earlier withdraw (i.e.,
+/// it is still unspent). With [Unit::Seconds], deposits and withdraws with unknown timestamp are
+/// ignored.
+///
+/// Notice that this function does not check to which pools deposit/withdraw calls were sent - the
+/// slices must contain the transactions of a single pool, sorted by block number (as returned by
+/// [collect_pools](crate::helpers::collect_pools)).
+///
+/// # Arguments
+///
+/// * `deposits` - a slice of references to Deposit structures to scan
+/// * `withdraws` - a slice of references to Withdraw structures to scan
+/// * `window` - maximum distance between a deposit and a withdraw
+/// * `max_addresses` - maximum number of addresses that made the candidate deposits
+/// * `unit` - whether the window is measured in blocks or seconds
+pub fn get_timing_matches<'a>(
+    deposits: &[&'a Deposit],
+    withdraws: &[&'a Withdraw],
+    window: u128,
+    max_addresses: usize,
+    unit: Unit,
+) -> Vec<(&'a Withdraw, Vec<&'a Deposit>)> {
+    let positioned: Vec<(u128, &Deposit)> = deposits
+        .iter()
+        .filter_map(|d| unit.position(*d).map(|p| (p, *d)))
+        .collect();
+    // deposits are identified by their index, since a transaction can contain several deposits
+    let mut spent = vec![false; positioned.len()];
+
+    withdraws
+        .iter()
+        .filter_map(|w| {
+            let position = unit.position(*w)?;
+            // deposits are sorted, so the candidates can be found by binary search
+            let start = positioned.partition_point(|(p, _)| p.saturating_add(window) < position);
+            let end = positioned.partition_point(|(p, _)| *p < position);
+            let candidates: Vec<usize> = (start..end).filter(|i| !spent[*i]).collect();
+            let addresses = candidates
+                .iter()
+                .map(|i| positioned[*i].1.from)
+                .unique()
+                .count();
+
+            if candidates.len() == 1 {
+                spent[candidates[0]] = true;
+            }
+            if addresses > 0 && addresses <= max_addresses {
+                Some((*w, candidates.iter().map(|i| positioned[*i].1).collect()))
             } else {
                 None
             }
@@ -104,82 +242,575 @@ pub fn get_address_matches<'a>(
         .collect()
 }
 
+/// Returns pairs of a depositor and a withdrawer that transacted directly with each other or were
+/// funded by the same address outside of Tornado Cash, together with the connecting transactions.
+/// Only pairs that used a common pool are returned (the depositor deposited to a pool the
+/// withdrawer withdrew from), an address is never linked to itself.
+///
+/// # Arguments
+///
+/// * `deposits` - a slice of references to Deposit structures to scan
+/// * `withdraws` - a slice of references to Withdraw structures to scan
+/// * `history` - transactions of the depositing and withdrawing addresses
+/// * `max_funded` - funders that sent funds to more depositors/withdrawers than this are ignored
+///   (e.g., exchanges)
+pub fn get_linked_addresses<'a>(
+    deposits: &[&Deposit],
+    withdraws: &[&Withdraw],
+    history: &[&'a ESNormalTransaction],
+    max_funded: usize,
+) -> Vec<AddressLink<'a>> {
+    let deposit_pools: HashMap<H160, HashSet<Pool>> =
+        deposits.iter().fold(HashMap::new(), |mut m, d| {
+            m.entry(d.from).or_insert_with(HashSet::new).insert(d.pool);
+            m
+        });
+    let withdraw_pools: HashMap<H160, HashSet<Pool>> =
+        withdraws.iter().fold(HashMap::new(), |mut m, w| {
+            m.entry(w.receiver)
+                .or_insert_with(HashSet::new)
+                .insert(w.pool);
+            m
+        });
+    let is_tornado = |chain: u64, a: H160| {
+        registry().pool_by_address(chain, a) != Pool::UNKNOWN || registry().is_router(chain, a)
+    };
+    // transactions between two different addresses, none of them a Tornado Cash contract
+    let transactions: Vec<(H160, H160, &ESNormalTransaction)> = history
+        .iter()
+        .filter_map(|t| t.to.map(|to| (t.from, to, *t)))
+        .filter(|(from, to, t)| {
+            from != to && !is_tornado(t.chain, *from) && !is_tornado(t.chain, *to)
+        })
+        .collect();
+
+    let mut links: BTreeMap<(H160, H160), AddressLink> = BTreeMap::new();
+    let new_link = |depositor, withdrawer| AddressLink {
+        depositor,
+        withdrawer,
+        transactions: vec![],
+        funders: vec![],
+    };
+
+    // depositor and withdrawer transacted directly (in either direction)
+    transactions
+        .iter()
+        .flat_map(|(from, to, t)| [(*from, *to, *t), (*to, *from, *t)])
+        .filter(|(d, w, _)| deposit_pools.contains_key(d) && withdraw_pools.contains_key(w))
+        .for_each(|(d, w, t)| {
+            links
+                .entry((d, w))
+                .or_insert_with(|| new_link(d, w))
+                .transactions
+                .push(t)
+        });
+
+    // depositor and withdrawer received funds from the same address
+    let funded: BTreeMap<H160, Vec<(H160, &ESNormalTransaction)>> = transactions
+        .iter()
+        .filter(|(_, to, t)| {
+            t.value > 0 && (deposit_pools.contains_key(to) || withdraw_pools.contains_key(to))
+        })
+        .fold(BTreeMap::new(), |mut m, (from, to, t)| {
+            m.entry(*from).or_insert_with(Vec::new).push((*to, *t));
+            m
+        });
+    funded
+        .into_iter()
+        .filter(|(_, ts)| ts.iter().map(|(a, _)| a).unique().count() <= max_funded)
+        .for_each(|(funder, ts)| {
+            ts.iter()
+                .filter(|(d, _)| deposit_pools.contains_key(d))
+                .cartesian_product(ts.iter().filter(|(w, _)| withdraw_pools.contains_key(w)))
+                .filter(|((d, _), (w, _))| d != w)
+                .for_each(|((d, dt), (w, wt))| {
+                    let l = links.entry((*d, *w)).or_insert_with(|| new_link(*d, *w));
+                    l.transactions.extend([*dt, *wt]);
+                    l.funders.push(funder);
+                })
+        });
+
+    links
+        .into_iter()
+        // the link is only relevant if the addresses used a common pool
+        .filter(|((d, w), _)| !deposit_pools[d].is_disjoint(&withdraw_pools[w]))
+        .map(|(_, l)| AddressLink {
+            transactions: l
+                .transactions
+                .into_iter()
+                .unique_by(|t| (t.chain, t.hash))
+                .sorted_by_key(|t| (t.chain, t.blockNumber, t.transactionIndex))
+                .collect(),
+            funders: l.funders.into_iter().unique().collect(),
+            ..l
+        })
+        .collect()
+}
+
+/// Returns the anonymity set of each withdraw: the number of deposits to the pool before the
+/// block of the withdraw, and the number of these deposits that are not contained in `linked`
+/// (i.e., that were not already linked to an address by a heuristic).
+///
+/// Notice that this function does not check to which pools deposit/withdraw calls were sent - the
+/// slices must contain the transactions of a single pool, sorted by block number (as returned by
+/// [collect_pools](crate::helpers::collect_pools)).
+///
+/// # Arguments
+///
+/// * `deposits` - a slice of references to Deposit structures to scan
+/// * `withdraws` - a slice of references to Withdraw structures to scan
+/// * `linked` - hashes of deposits that are not counted for the effective anonymity set
+pub fn get_anonymity_sets<'a>(
+    deposits: &[&Deposit],
+    withdraws: &[&'a Withdraw],
+    linked: &HashSet<H256>,
+) -> Vec<AnonymitySet<'a>> {
+    // number of linked deposits among the first i deposits
+    let linked_before: Vec<usize> = [0]
+        .into_iter()
+        .chain(deposits.iter().scan(0, |n, d| {
+            *n += linked.contains(&d.transaction_hash) as usize;
+            Some(*n)
+        }))
+        .collect();
+
+    withdraws
+        .iter()
+        .map(|w| {
+            let size = deposits.partition_point(|d| d.block_number < w.block_number);
+
+            AnonymitySet {
+                withdraw: w,
+                size,
+                effective: size - linked_before[size],
+            }
+        })
+        .collect()
+}
+
+/// Returns statistics of the relayers that sent withdraws from a pool: the number of withdraws,
+/// their receivers, the first and last block, the distribution of the fees and the market share
+/// of the relayer in each period of `interval` blocks. Every withdraw with a relayer is considered,
+/// including self-relayed ones and ones without fee (an unusual fee as well). The relayers are
+/// sorted by the number of withdraws (most first).
+///
+/// Notice that this function does not check to which pools withdraw calls were sent - the slice
+/// must contain the withdraws of a single pool.
+///
+/// # Arguments
+///
+/// * `withdraws` - a slice of references to Withdraw structures to scan
+/// * `interval` - number of blocks of a period for the market shares
+pub fn get_relayer_stats(withdraws: &[&Withdraw], interval: u128) -> Vec<RelayerStats> {
+    let interval = interval.max(1);
+    let period = |w: &Withdraw| w.block_number - w.block_number % interval;
+    // fees do not necessarily fit into 128 bits, but realistic ones do
+    let fee = |w: &Withdraw| u128::try_from(w.fee).unwrap_or(u128::MAX);
+
+    let sent: Vec<&Withdraw> = withdraws
+        .iter()
+        .filter(|w| !w.relayer.is_zero())
+        .copied()
+        .collect();
+    let by_period = sent.iter().copied().into_group_map_by(|w| period(w));
+    // median fee of all withdraws with a relayer in each period
+    let median_fees: HashMap<u128, u128> = by_period
+        .iter()
+        .map(|(p, ws)| {
+            let fees: Vec<u128> = ws.iter().map(|w| fee(w)).collect();
+            (*p, FeeDistribution::from(&fees[..]).median)
+        })
+        .collect();
+
+    sent.iter()
+        .copied()
+        .into_group_map_by(|w| w.relayer)
+        .into_iter()
+        .map(|(relayer, ws)| {
+            let fees: Vec<u128> = ws.iter().map(|w| fee(w)).collect();
+            let ratios: Vec<f64> = ws
+                .iter()
+                .map(|w| fee(w) as f64 / median_fees[&period(w)].max(1) as f64)
+                .sorted_by(f64::total_cmp)
+                .collect();
+
+            RelayerStats {
+                relayer,
+                pool: ws[0].pool,
+                withdraws: ws.len(),
+                receivers: ws.iter().map(|w| w.receiver).unique().sorted().collect(),
+                first_block: ws.iter().map(|w| w.block_number).min().unwrap_or_default(),
+                last_block: ws.iter().map(|w| w.block_number).max().unwrap_or_default(),
+                fees: FeeDistribution::from(&fees[..]),
+                relative_fee: ratios[ratios.len() / 2],
+                market_share: ws
+                    .iter()
+                    .map(|w| period(w))
+                    .counts()
+                    .into_iter()
+                    .map(|(p, n)| (p, n as f64 / by_period[&p].len() as f64))
+                    .collect(),
+            }
+        })
+        .sorted_by(|a, b| {
+            b.withdraws
+                .cmp(&a.withdraws)
+                .then_with(|| a.relayer.cmp(&b.relayer))
+        })
+        .collect()
+}
+
 /// Get a vector of triples of two addresses and a certain (([deposit/withdraw pattern](DepositWithdrawPattern))
 /// between both addresses.
 ///
+/// A match is unique if exactly one address deposited and exactly one address withdrew the
+/// pattern, which is looked up in indexes from patterns to addresses. The triples are sorted by
+/// the addresses.
+///
+/// Only deposit patterns that contain at least `min_repeat` deposits to one pool and deposits to
+/// at least `min_pools` different pools are considered.
+///
 /// # Arguments
 ///
 /// * `deposits` - a slice of references to Deposit structures to scan
 /// * `withdraws` - a slice of references to Withdraw structures to scan
+/// * `min_repeat` - minimum number of deposits to the same pool
+/// * `min_pools` - minimum number of different pools
 pub fn match_patterns(
     deposits: &[&Deposit],
     withdraws: &[&Withdraw],
+    min_repeat: u64,
+    min_pools: usize,
 ) -> Vec<(H160, H160, DepositWithdrawPattern)> {
-    // get a mapping from addresses to deposits made by this address
-    let depositors: HashMap<_, _> =
-        HashMap::from_iter(deposits.iter().map(|d| d.from).unique().map(|a| {
-            (
-                a,
-                deposits
-                    .iter()
-                    .filter(|d| d.from == a)
-                    .copied()
-                    .collect::<Vec<&Deposit>>(),
-            )
-        }));
+    // get mappings from addresses to their deposits/withdraws and patterns
+    let depositors = patterns_by_address(deposits, |d| d.from);
+    let withdrawers = patterns_by_address(withdraws, |w| w.receiver);
 
-    // get a mapping from addresses to withdraws made by this address
-    let withdrawers: HashMap<_, _> =
-        HashMap::from_iter(withdraws.iter().map(|d| d.receiver).unique().map(|a| {
-            (
-                a,
-                withdraws
-                    .iter()
-                    .filter(|d| d.receiver == a)
-                    .copied()
-                    .collect::<Vec<&Withdraw>>(),
-            )
-        }));
+    // get mappings from patterns to the addresses that have them
+    let deposit_index = addresses_by_pattern(&depositors);
+    let withdraw_index = addresses_by_pattern(&withdrawers);
+
+    deposit_index
+        .iter()
+        // select only "interesting" patterns
+        .filter(|(p, _)| pattern_is_interesting(p, min_repeat, min_pools))
+        // select only matches that are unique, i.e., exactly one address deposited and exactly
+        // one address withdrew the pattern
+        .filter_map(|(p, das)| match (&das[..], &withdraw_index.get(p)?[..]) {
+            ([da], [wa]) => Some((*da, *wa, *p)),
+            _ => None,
+        })
+        // select only matches for which each deposit to a pool was made _before_ a withdraw from this pool
+        .filter(|(da, wa, _)| earlier(&depositors[da].0, &withdrawers[wa].0))
+        .map(|(da, wa, p)| (da, wa, p.clone()))
+        .sorted_by_key(|(da, wa, _)| (*da, *wa))
+        .collect()
+}
 
-    // get deposit patterns
-    let deposit_patterns: Vec<(H160, DepositWithdrawPattern)> =
-        depositors.iter().map(|(a, ds)| (*a, ds.into())).collect();
+// sliding windows over transactions: for each transaction, the transactions made at most `window`
+// (in `unit`) after it, windows contained in the window of the previous transaction are skipped
+fn windows<'a, T: InBlock>(transactions: &[&'a T], window: u128, unit: Unit) -> Vec<Vec<&'a T>> {
+    let positioned: Vec<(u128, &T)> = transactions
+        .iter()
+        .filter_map(|t| unit.position(*t).map(|p| (p, *t)))
+        .sorted_by_key(|(p, _)| *p)
+        .collect();
 
-    // get withdraw patterns
-    let withdraw_patterns: Vec<(H160, DepositWithdrawPattern)> =
-        withdrawers.iter().map(|(a, ws)| (*a, ws.into())).collect();
+    let mut end = 0;
+    let mut res = vec![];
+    for (i, (p, _)) in positioned.iter().enumerate() {
+        let last = positioned.partition_point(|(q, _)| *q <= p.saturating_add(window));
+        if last > end {
+            res.push(positioned[i..last].iter().map(|(_, t)| *t).collect());
+            end = last;
+        }
+    }
+    res
+}
 
-    // search matching patterns
-    let pattern_matches: Vec<(
-        H160,                    // depositing address
-        H160,                    // withdrawing address
-        &DepositWithdrawPattern, // deposit pattern
-        &DepositWithdrawPattern, // withdraw pattern
-    )> = deposit_patterns
+/// Like [match_patterns], but patterns are not formed by all deposits/withdraws of an address:
+/// for each deposit (withdraw) of an address, its deposits (withdraws) made at most `window`
+/// blocks or seconds (see `unit`) later form a sliding window. A deposit window matches a withdraw
+/// window with the same pattern if all withdraws were made after the deposits, at most `max_delay`
+/// after the last deposit. A match is unique if no other address has a window with the same
+/// pattern that satisfies these constraints for either of them. Each withdraw window is matched
+/// at most once (with the latest matching deposit window).
+///
+/// # Arguments
+///
+/// * `deposits` - a slice of references to Deposit structures to scan
+/// * `withdraws` - a slice of references to Withdraw structures to scan
+/// * `min_repeat` - minimum number of deposits to the same pool
+/// * `min_pools` - minimum number of different pools
+/// * `window` - maximum distance between the deposits (withdraws) of a pattern
+/// * `max_delay` - maximum distance between the last deposit and the first withdraw
+/// * `unit` - whether distances are measured in blocks or seconds
+pub fn match_patterns_in_windows<'a>(
+    deposits: &[&'a Deposit],
+    withdraws: &[&'a Withdraw],
+    min_repeat: u64,
+    min_pools: usize,
+    window: u128,
+    max_delay: u128,
+    unit: Unit,
+) -> Vec<PatternMatch<'a>> {
+    // windows of each address together with their pattern
+    let deposit_windows: Vec<(H160, DepositWithdrawPattern, Vec<&Deposit>)> = deposits
         .iter()
-        // select only "interesting" patterns
-        .filter(|(_, dp)| pattern_is_interesting(dp))
-        // for each deposit pattern find all equal withdraw patterns
-        .flat_map(|(a, dp)| {
-            withdraw_patterns
-                .iter()
-                .filter_map(move |(a_, wp)| if wp == dp { Some((a_, wp)) } else { None })
-                .map(move |(a_, wp)| (*a, *a_, wp, dp))
+        .copied()
+        .into_group_map_by(|d| d.from)
+        .into_iter()
+        .flat_map(|(a, ds)| {
+            windows(&ds, window, unit)
+                .into_iter()
+                .map(move |w| (a, (&w).into(), w))
         })
+        .filter(|(_, p, _)| pattern_is_interesting(p, min_repeat, min_pools))
         .collect();
+    // withdraw windows of each address by their pattern
+    let withdraw_windows = withdraws
+        .iter()
+        .copied()
+        .into_group_map_by(|w| w.receiver)
+        .into_iter()
+        .flat_map(|(a, ws)| windows(&ws, window, unit).into_iter().map(move |w| (a, w)))
+        .into_group_map_by(|(_, w)| DepositWithdrawPattern::from(w));
+
+    let deposits_by_pattern: HashMap<&DepositWithdrawPattern, Vec<(H160, &[&Deposit])>> =
+        deposit_windows
+            .iter()
+            .map(|(a, p, ds)| (p, (*a, &ds[..])))
+            .into_group_map();
 
-    pattern_matches
+    // withdraw windows that could have been funded by a deposit window (all deposits/withdraws
+    // in windows have a position)
+    let follows = |ds: &[&Deposit], ws: &[&Withdraw]| {
+        let last = unit.position(ds[ds.len() - 1]).unwrap_or_default();
+        let first = unit.position(ws[0]).unwrap_or_default();
+        first > last && first - last <= max_delay
+    };
+    let candidates = |ds: &[&Deposit], p: &DepositWithdrawPattern| {
+        withdraw_windows
+            .get(p)
+            .map(|ws| {
+                ws.iter()
+                    .filter(|(_, ws)| follows(ds, ws))
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default()
+    };
+
+    deposit_windows
         .iter()
-        .filter(|(_, _, dp, _)| {
-            // select only matches that are unique
-            pattern_matches
+        .filter_map(|(a, p, ds)| {
+            let cs = candidates(ds, p);
+            // the withdraw windows after the deposits must belong to a single address (its first
+            // window is matched)...
+            match cs.iter().map(|(b, _)| b).unique().count() {
+                1 => Some((a, p, ds, &cs[0].0, &cs[0].1)),
+                _ => None,
+            }
+        })
+        // ...and must not follow a window of another depositor with this pattern
+        .filter(|(_, p, _, _, ws)| {
+            deposits_by_pattern[p]
                 .iter()
-                .filter(|(_, _, dp_, _)| dp == dp_)
+                .filter(|(_, ds)| follows(ds, ws))
+                .map(|(a, _)| a)
+                .unique()
                 .count()
                 == 1
         })
-        // select only matches for which each deposit to a pool was made _before_ a withdraw from this pool
-        .filter(|(da, wa, _, _)| earlier(depositors.get(da).unwrap(), withdrawers.get(wa).unwrap()))
-        .map(move |(da, wa, p, _)| (*da, *wa, **p))
+        .map(|(a, p, ds, b, ws)| PatternMatch {
+            depositor: *a,
+            withdrawer: *b,
+            pattern: p.clone(),
+            deposits: ds.clone(),
+            withdraws: ws.clone(),
+        })
+        .sorted_by_key(|m| (m.deposits[0].block_number, m.depositor))
+        .rev()
+        // windows of an address start at different transactions, so the first withdraw
+        // identifies a window
+        .unique_by(|m| {
+            (
+                m.withdrawer,
+                m.withdraws[0].chain,
+                m.withdraws[0].transaction_hash,
+            )
+        })
+        .sorted_by_key(|m| (m.deposits[0].block_number, m.depositor))
+        .collect()
+}
+
+// deposits/withdraws of each address together with their pattern
+fn patterns_by_address<'a, T: InPool>(
+    transactions: &[&'a T],
+    address: impl Fn(&T) -> H160,
+) -> HashMap<H160, (Vec<&'a T>, DepositWithdrawPattern)> {
+    transactions
+        .iter()
+        .copied()
+        .into_group_map_by(|t| address(t))
+        .into_iter()
+        .map(|(a, ts)| {
+            let p = (&ts).into();
+            (a, (ts, p))
+        })
+        .collect()
+}
+
+// addresses (sorted) by their pattern
+fn addresses_by_pattern<'a, T>(
+    patterns: &'a HashMap<H160, (Vec<&T>, DepositWithdrawPattern)>,
+) -> HashMap<&'a DepositWithdrawPattern, Vec<H160>> {
+    patterns
+        .iter()
+        .map(|(a, (_, p))| (p, *a))
+        .into_group_map()
+        .into_iter()
+        .map(|(p, addresses)| (p, addresses.into_iter().sorted().collect()))
+        .collect()
+}
+
+/// Returns addresses that withdrew a part of what another address deposited: the withdraw pattern
+/// is a proper subset of the deposit pattern (e.g., 2x 1 ETH of 3x 1 ETH and 1x 10 ETH). A match
+/// can be explained by any depositor whose pattern contains the withdraw pattern and any
+/// withdrawer with the same withdraw pattern, matches with more than `max_candidates` such
+/// combinations are dropped. The matches are sorted by the number of candidates (most unique
+/// first).
+///
+/// Only deposit patterns that contain at least `min_repeat` deposits to one pool and deposits to
+/// at least `min_pools` different pools are considered.
+///
+/// # Arguments
+///
+/// * `deposits` - a slice of references to Deposit structures to scan
+/// * `withdraws` - a slice of references to Withdraw structures to scan
+/// * `min_repeat` - minimum number of deposits to the same pool
+/// * `min_pools` - minimum number of different pools
+/// * `max_candidates` - maximum number of combinations of addresses that explain a match
+pub fn match_subset_patterns(
+    deposits: &[&Deposit],
+    withdraws: &[&Withdraw],
+    min_repeat: u64,
+    min_pools: usize,
+    max_candidates: usize,
+) -> Vec<PartialPatternMatch> {
+    let depositors = patterns_by_address(deposits, |d| d.from);
+    let withdrawers = patterns_by_address(withdraws, |w| w.receiver);
+    let deposit_groups: Vec<(&DepositWithdrawPattern, Vec<H160>)> =
+        addresses_by_pattern(&depositors)
+            .into_iter()
+            .filter(|(p, _)| pattern_is_interesting(p, min_repeat, min_pools))
+            .collect();
+
+    addresses_by_pattern(&withdrawers)
+        .into_iter()
+        .filter(|(wp, _)| !wp.counts.is_empty())
+        .flat_map(|(wp, was)| {
+            // all deposit patterns the withdraw pattern may have been taken from
+            let supersets: Vec<&(&DepositWithdrawPattern, Vec<H160>)> = deposit_groups
+                .iter()
+                .filter(|(dp, _)| wp.is_subset_of(dp))
+                .collect();
+            let candidates = supersets.iter().map(|(_, das)| das.len()).sum::<usize>() * was.len();
+
+            supersets
+                .into_iter()
+                .filter(|(dp, _)| *dp != wp && candidates <= max_candidates)
+                .flat_map(|(dp, das)| das.iter().map(move |da| (*dp, *da)))
+                .cartesian_product(was)
+                .filter(|((_, da), wa)| earlier(&depositors[da].0, &withdrawers[wa].0))
+                .map(|((dp, da), wa)| PartialPatternMatch {
+                    depositor: da,
+                    withdrawers: vec![wa],
+                    pattern: dp.clone(),
+                    withdraw_patterns: vec![wp.clone()],
+                    candidates,
+                })
+                .collect::<Vec<PartialPatternMatch>>()
+        })
+        .sorted_by_key(|m| (m.candidates, m.depositor, m.withdrawers.clone()))
+        .collect()
+}
+
+/// Returns addresses whose deposits were withdrawn by two other addresses: the withdraw patterns
+/// of both add up to the deposit pattern (e.g., 2x 1 ETH and 1x 10 ETH of 2x 1 ETH and 1x 10
+/// ETH). A match can be explained by any depositor with the same deposit pattern and any pair of
+/// withdrawers whose patterns add up to it, matches with more than `max_candidates` such
+/// combinations are dropped. The matches are sorted by the number of candidates (most unique
+/// first).
+///
+/// Only deposit patterns that contain at least `min_repeat` deposits to one pool and deposits to
+/// at least `min_pools` different pools are considered.
+///
+/// # Arguments
+///
+/// * `deposits` - a slice of references to Deposit structures to scan
+/// * `withdraws` - a slice of references to Withdraw structures to scan
+/// * `min_repeat` - minimum number of deposits to the same pool
+/// * `min_pools` - minimum number of different pools
+/// * `max_candidates` - maximum number of combinations of addresses that explain a match
+pub fn match_split_patterns(
+    deposits: &[&Deposit],
+    withdraws: &[&Withdraw],
+    min_repeat: u64,
+    min_pools: usize,
+    max_candidates: usize,
+) -> Vec<PartialPatternMatch> {
+    let depositors = patterns_by_address(deposits, |d| d.from);
+    let withdrawers = patterns_by_address(withdraws, |w| w.receiver);
+    let withdraw_index = addresses_by_pattern(&withdrawers);
+
+    addresses_by_pattern(&depositors)
+        .into_iter()
+        .filter(|(dp, _)| pattern_is_interesting(dp, min_repeat, min_pools))
+        .flat_map(|(dp, das)| {
+            // pairs of withdrawers whose patterns add up to the deposit pattern, each pair of
+            // patterns is only considered once (the smaller pattern first)
+            let splits: Vec<(H160, H160, &DepositWithdrawPattern, DepositWithdrawPattern)> =
+                withdraw_index
+                    .iter()
+                    .filter_map(|(wp, was)| {
+                        let rest = dp.difference(wp)?;
+                        let ras = withdraw_index.get(&rest)?;
+                        (!wp.counts.is_empty()
+                            && !rest.counts.is_empty()
+                            && wp.counts <= rest.counts)
+                            .then_some((*wp, was, rest, ras))
+                    })
+                    .flat_map(|(wp, was, rest, ras)| {
+                        was.iter()
+                            .cartesian_product(ras.iter())
+                            .filter(|(b, c)| wp.counts != rest.counts || b < c)
+                            .map(|(b, c)| (*b, *c, wp, rest.clone()))
+                            .collect::<Vec<_>>()
+                    })
+                    .collect();
+            let candidates = das.len() * splits.len();
+
+            das.into_iter()
+                .filter(|_| candidates <= max_candidates)
+                .cartesian_product(splits)
+                .filter(|(da, (b, c, _, _))| {
+                    let ws: Vec<&Withdraw> = withdrawers[b]
+                        .0
+                        .iter()
+                        .chain(withdrawers[c].0.iter())
+                        .copied()
+                        .collect();
+                    earlier(&depositors[da].0, &ws)
+                })
+                .map(|(da, (b, c, bp, cp))| PartialPatternMatch {
+                    depositor: da,
+                    withdrawers: vec![b, c],
+                    pattern: dp.clone(),
+                    withdraw_patterns: vec![bp.clone(), cp],
+                    candidates,
+                })
+                .collect::<Vec<PartialPatternMatch>>()
+        })
+        .sorted_by_key(|m| (m.candidates, m.depositor, m.withdrawers.clone()))
         .collect()
 }
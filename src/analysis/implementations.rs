@@ -1,25 +1,173 @@
-use super::DepositWithdrawPattern;
-use crate::data::{InPool, Pool};
+use super::{DepositWithdrawPattern, FeeDistribution, PatternFrequencies, RelayerStats, Unit};
+use crate::data::{Deposit, InBlock, InPool, Pool, AMOUNT_UNITS};
+use itertools::Itertools;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::fmt::{Display, Formatter, Result as FmtResult};
 
 impl<T: InPool> From<&Vec<&T>> for DepositWithdrawPattern {
     fn from(transactions: &Vec<&T>) -> Self {
-        #[allow(non_snake_case)]
-        let (n0_1ETH, n1ETH, n10ETH, n100ETH) = transactions.iter().fold(
-            (0u64, 0u64, 0u64, 0u64),
-            |(_0_1eth, _1eth, _10eth, _100eth), t| match t.pool() {
-                Pool::_0_1ETH => (_0_1eth + 1, _1eth, _10eth, _100eth),
-                Pool::_1ETH => (_0_1eth, _1eth + 1, _10eth, _100eth),
-                Pool::_10ETH => (_0_1eth, _1eth, _10eth + 1, _100eth),
-                Pool::_100ETH => (_0_1eth, _1eth, _10eth, _100eth + 1),
-                Pool::Unknown => (_0_1eth, _1eth, _10eth, _100eth),
-            },
-        );
-
-        DepositWithdrawPattern {
-            n0_1ETH,
-            n1ETH,
-            n10ETH,
-            n100ETH,
+        let counts = transactions
+            .iter()
+            .map(|t| *t.pool())
+            .filter(|p| *p != Pool::UNKNOWN)
+            .fold(BTreeMap::new(), |mut counts, p| {
+                *counts.entry(p).or_insert(0) += 1;
+                counts
+            });
+
+        DepositWithdrawPattern { counts }
+    }
+}
+
+impl DepositWithdrawPattern {
+    /// Check whether each pool of this pattern is contained in `other` at least as often
+    pub fn is_subset_of(&self, other: &DepositWithdrawPattern) -> bool {
+        self.counts
+            .iter()
+            .all(|(p, n)| other.counts.get(p).is_some_and(|m| n <= m))
+    }
+
+    /// The pattern that remains if `other` is removed from this pattern, `None` if `other` is not
+    /// a subset of this pattern
+    pub fn difference(&self, other: &DepositWithdrawPattern) -> Option<DepositWithdrawPattern> {
+        if !other.is_subset_of(self) {
+            return None;
+        }
+
+        Some(DepositWithdrawPattern {
+            counts: self
+                .counts
+                .iter()
+                .map(|(p, n)| (*p, n - other.counts.get(p).unwrap_or(&0)))
+                .filter(|(_, n)| *n > 0)
+                .collect(),
+        })
+    }
+
+    /// Total amount deposited/withdrawn per asset
+    pub fn amounts(&self) -> BTreeMap<&str, f64> {
+        // added up in units of the asset, e.g., 3x 0.1 ETH are exactly 0.3 ETH
+        self.counts
+            .iter()
+            .filter_map(|(p, n)| Some((p.asset()?, p.denomination_units()? * *n as u128)))
+            .fold(BTreeMap::new(), |mut amounts, (a, v)| {
+                *amounts.entry(a).or_insert(0) += v;
+                amounts
+            })
+            .into_iter()
+            .map(|(a, v)| (a, v as f64 / AMOUNT_UNITS as f64))
+            .collect()
+    }
+}
+
+impl Display for DepositWithdrawPattern {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        write!(
+            f,
+            "{} = {}",
+            self.counts
+                .iter()
+                .map(|(p, n)| format!("{}x {}", n, p))
+                .collect::<Vec<String>>()
+                .join(", "),
+            self.amounts()
+                .iter()
+                .map(|(a, v)| format!("{} {}", v, a))
+                .collect::<Vec<String>>()
+                .join(", ")
+        )
+    }
+}
+
+impl From<&[&Deposit]> for PatternFrequencies {
+    fn from(deposits: &[&Deposit]) -> Self {
+        let patterns: Vec<DepositWithdrawPattern> = deposits
+            .iter()
+            .copied()
+            .into_group_map_by(|d| d.from)
+            .values()
+            .map(|ds| ds.into())
+            .collect();
+
+        PatternFrequencies {
+            depositors: patterns.len(),
+            counts: patterns
+                .iter()
+                .flat_map(|p| p.counts.iter().map(|(pool, n)| (*pool, *n)))
+                .fold(HashMap::new(), |mut counts, k| {
+                    *counts.entry(k).or_insert(0) += 1;
+                    counts
+                }),
+        }
+    }
+}
+
+impl PatternFrequencies {
+    /// Estimated probability that a depositor picked at random has `pattern`, assuming that the
+    /// numbers of deposits to different pools are independent
+    pub fn probability(&self, pattern: &DepositWithdrawPattern) -> f64 {
+        if self.depositors == 0 {
+            return 0.0;
+        }
+
+        pattern
+            .counts
+            .iter()
+            .map(|(p, n)| {
+                self.counts.get(&(*p, *n)).copied().unwrap_or_default() as f64
+                    / self.depositors as f64
+            })
+            .product()
+    }
+
+    /// Confidence (between 0 and 1) that a match of `pattern` is not a coincidence: 1 / (1 + E),
+    /// where E is the expected number of further depositors that have the pattern by chance
+    pub fn score(&self, pattern: &DepositWithdrawPattern) -> f64 {
+        let expected = self.depositors.saturating_sub(1) as f64 * self.probability(pattern);
+
+        1.0 / (1.0 + expected)
+    }
+}
+
+impl From<&[u128]> for FeeDistribution {
+    fn from(fees: &[u128]) -> Self {
+        let sorted: Vec<u128> = fees.iter().copied().sorted().collect();
+
+        FeeDistribution {
+            min: sorted.first().copied().unwrap_or_default(),
+            median: sorted.get(sorted.len() / 2).copied().unwrap_or_default(),
+            max: sorted.last().copied().unwrap_or_default(),
         }
     }
 }
+
+impl Unit {
+    /// Parse the value of a `unit` parameter ("blocks" or "seconds")
+    pub fn parse(unit: &str) -> Result<Self, Box<dyn Error>> {
+        match unit {
+            "blocks" => Ok(Unit::Blocks),
+            "seconds" => Ok(Unit::Seconds),
+            _ => Err(format!("unknown unit '{}'", unit).into()),
+        }
+    }
+
+    /// Position of a deposit/withdraw in this unit: its block number or its timestamp (`None` if
+    /// the timestamp is unknown)
+    pub fn position<T: InBlock>(&self, t: &T) -> Option<u128> {
+        match self {
+            Unit::Blocks => Some(t.block()),
+            Unit::Seconds => t.timestamp(),
+        }
+    }
+}
+
+impl RelayerStats {
+    /// Check whether the relayer's fees deviate from the usual fees by more than `fee_factor`
+    /// (in either direction) while it served at most `max_receivers` receivers, i.e., whether its
+    /// fees can be used to recognize the withdraws of these few users
+    pub fn fingerprints(&self, fee_factor: f64, max_receivers: usize) -> bool {
+        (self.relative_fee > fee_factor || self.relative_fee * fee_factor < 1.0)
+            && self.receivers.len() <= max_receivers
+    }
+}
@@ -1,16 +1,106 @@
 mod implementations;
 
-use hex_literal::hex;
+use crate::data::{Deposit, ESNormalTransaction, Pool, Withdraw};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use web3::types::H160;
 pub mod analyze;
 pub mod prepare;
 
-const TORNADO_CASH_ROUTER: [u8; 20] = hex!("d90e2f925DA726b50C4Ed8D0Fb90Ad053324F31b");
+/// Unit in which windows and delays between deposits/withdraws are measured
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unit {
+    /// Block numbers
+    Blocks,
+    /// Block timestamps, deposits/withdraws with unknown timestamp are ignored
+    Seconds,
+}
 
-#[derive(PartialEq, Debug, Copy, Clone)]
-#[allow(non_snake_case)]
+/// Number of deposits/withdraws an address made to/from each pool. Pools the address never
+/// used are not contained in the map.
+#[derive(Serialize, PartialEq, Eq, Hash, Debug, Clone)]
 pub struct DepositWithdrawPattern {
-    pub n0_1ETH: u64,
-    pub n1ETH: u64,
-    pub n10ETH: u64,
-    pub n100ETH: u64,
+    pub counts: BTreeMap<Pool, u64>,
+}
+
+/// A depositor and a withdrawer that are connected by transactions outside of Tornado Cash,
+/// either directly or through a common funding address
+#[derive(Debug, Clone)]
+pub struct AddressLink<'a> {
+    pub depositor: H160,
+    pub withdrawer: H160,
+    /// Transactions connecting the two addresses, sorted by chain and block
+    pub transactions: Vec<&'a ESNormalTransaction>,
+    /// Addresses that sent funds to both the depositor and the withdrawer
+    pub funders: Vec<H160>,
+}
+
+/// Number of deposits that could have funded a withdraw
+#[derive(Debug, Clone)]
+pub struct AnonymitySet<'a> {
+    pub withdraw: &'a Withdraw,
+    /// Number of deposits to the pool before the block of the withdraw
+    pub size: usize,
+    /// Number of these deposits that are not linked to an address by a heuristic
+    pub effective: usize,
+}
+
+/// Deposits of one address and withdraws of another address with the same pattern
+#[derive(Debug, Clone)]
+pub struct PatternMatch<'a> {
+    pub depositor: H160,
+    pub withdrawer: H160,
+    pub pattern: DepositWithdrawPattern,
+    pub deposits: Vec<&'a Deposit>,
+    pub withdraws: Vec<&'a Withdraw>,
+}
+
+/// Deposits of one address that were (partially) withdrawn by one or more other addresses
+#[derive(Debug, Clone)]
+pub struct PartialPatternMatch {
+    pub depositor: H160,
+    pub withdrawers: Vec<H160>,
+    /// Pattern of the deposits
+    pub pattern: DepositWithdrawPattern,
+    /// Patterns of the withdraws of each withdrawer
+    pub withdraw_patterns: Vec<DepositWithdrawPattern>,
+    /// Number of combinations of addresses with the same patterns that could explain the match
+    /// (1 if the match is unique)
+    pub candidates: usize,
+}
+
+/// Number of depositors by the number of deposits they made to a pool, used to estimate how rare
+/// a [deposit/withdraw pattern](DepositWithdrawPattern) is
+#[derive(Debug, Clone, Default)]
+pub struct PatternFrequencies {
+    pub depositors: usize,
+    /// Number of depositors that made exactly n deposits to a pool, by (pool, n)
+    pub counts: HashMap<(Pool, u64), usize>,
+}
+
+/// Minimum, median and maximum of fees (in the smallest unit of the pool's asset)
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct FeeDistribution {
+    pub min: u128,
+    pub median: u128,
+    pub max: u128,
+}
+
+/// Withdraws a relayer sent for a pool
+#[derive(Serialize, Debug, Clone)]
+pub struct RelayerStats {
+    pub relayer: H160,
+    pub pool: Pool,
+    pub withdraws: usize,
+    /// Addresses that received the withdraws, sorted
+    pub receivers: Vec<H160>,
+    pub first_block: u128,
+    pub last_block: u128,
+    pub fees: FeeDistribution,
+    /// Median ratio of the relayer's fees to the median fee of all relayed withdraws from the pool
+    /// in the same period (1 if the relayer charges the usual fees)
+    pub relative_fee: f64,
+    /// Share of the relayed withdraws from the pool sent by the relayer, by period (first block
+    /// of the period). Periods without withdraws by the relayer are not contained.
+    pub market_share: BTreeMap<u128, f64>,
 }
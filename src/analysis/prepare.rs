@@ -1,89 +1,243 @@
-use super::TORNADO_CASH_ROUTER;
-use crate::data::{Deposit, ESNormalTransaction, PoolCall, RouterCall, Withdraw};
-use crate::immut_append;
-
-fn router_call(
-    call: &ESNormalTransaction,
-    dep: Vec<Deposit>,
-    wit: Vec<Withdraw>,
-) -> (Vec<Deposit>, Vec<Withdraw>) {
-    let rc: RouterCall = call.input.as_ref().unwrap()[..].into();
+use crate::data::registry::registry;
+use crate::data::{
+    Deposit, ESNormalTransaction, PoolCall, PoolEvent, PoolLog, RouterCall, Withdraw,
+};
+use crate::error::Error;
+use std::collections::{HashMap, HashSet};
+use web3::types::{H160, H256};
+
+/// Deposits and withdraws decoded from calls or logs, together with the errors of calls/logs
+/// that could not be decoded
+pub type Decoded = (Vec<Deposit>, Vec<Withdraw>, Vec<Error>);
+
+fn decode_error(chain: u64, hash: H256, e: Box<dyn std::error::Error>) -> Error {
+    Error::Decode {
+        chain,
+        hash,
+        reason: e.to_string(),
+        entry: None,
+    }
+}
+
+fn router_call(call: &ESNormalTransaction, (dep, wit, err): &mut Decoded) {
+    // calls without input (i.e., plain transfers) are no deposits/withdraws
+    let rc: RouterCall = match call.input.as_deref().unwrap_or_default().try_into() {
+        Ok(rc) => rc,
+        Err(e) => {
+            err.push(decode_error(call.chain, call.hash, e));
+            return;
+        }
+    };
 
     match rc {
-        RouterCall::Withdraw(w) => (
-            dep,
-            immut_append!(
-                wit,
-                Withdraw::new(
-                    call.hash,
-                    call.blockNumber,
-                    w._tornado,
-                    w._recipient,
-                    w._relayer,
-                    w._fee,
-                )
-            ),
+        RouterCall::Withdraw(w) => wit.push(
+            Withdraw::new(
+                call.chain,
+                call.hash,
+                call.blockNumber,
+                w._tornado,
+                w._recipient,
+                w._relayer,
+                w._fee,
+            )
+            .with_gas_price(call.gasPrice)
+            .with_timestamp(call.timeStamp),
         ),
-        RouterCall::Deposit(d) => {
-            let pool_addr: &[u8; 20] = d._tornado[..].try_into().unwrap();
-
-            (
-                immut_append!(
-                    dep,
-                    Deposit::new(call.hash, call.blockNumber, pool_addr.into(), call.from,)
-                ),
-                wit,
+        RouterCall::Deposit(d) => dep.push(
+            Deposit::new(
+                call.chain,
+                call.hash,
+                call.blockNumber,
+                d._tornado,
+                call.from,
             )
-        }
-        _ => (dep, wit),
+            .with_gas_price(call.gasPrice)
+            .with_timestamp(call.timeStamp),
+        ),
+        _ => {}
     }
 }
 
-fn pool_call(
-    call: &ESNormalTransaction,
-    dep: Vec<Deposit>,
-    wit: Vec<Withdraw>,
-) -> (Vec<Deposit>, Vec<Withdraw>) {
-    let rc: PoolCall = call.input.as_ref().unwrap()[..].into();
+fn pool_call(call: &ESNormalTransaction, to: H160, (dep, wit, err): &mut Decoded) {
+    let rc: PoolCall = match call.input.as_deref().unwrap_or_default().try_into() {
+        Ok(rc) => rc,
+        Err(e) => {
+            err.push(decode_error(call.chain, call.hash, e));
+            return;
+        }
+    };
 
     match rc {
-        PoolCall::Withdraw(w) => (
-            dep,
-            immut_append!(
-                wit,
-                Withdraw::new(
-                    call.hash,
-                    call.blockNumber,
-                    call.to.unwrap(),
-                    w._recipient,
-                    w._relayer,
-                    w._fee,
-                )
-            ),
+        PoolCall::Withdraw(w) => wit.push(
+            Withdraw::new(
+                call.chain,
+                call.hash,
+                call.blockNumber,
+                to,
+                w._recipient,
+                w._relayer,
+                w._fee,
+            )
+            .with_gas_price(call.gasPrice)
+            .with_timestamp(call.timeStamp),
         ),
-        PoolCall::Deposit(_) => (
-            immut_append!(
-                dep,
-                Deposit::new(call.hash, call.blockNumber, call.to.unwrap(), call.from,)
-            ),
-            wit,
+        PoolCall::Deposit => dep.push(
+            Deposit::new(call.chain, call.hash, call.blockNumber, to, call.from)
+                .with_gas_price(call.gasPrice)
+                .with_timestamp(call.timeStamp),
         ),
-        _ => (dep, wit),
+        _ => {}
     }
 }
 
 /// Parse transactions contract function calls. Whether the input of a call should be (tried)
 /// to be parsed as a call to a router or as a "direct" call to a pool is decided by
-/// checking if the receiver has the Tornado Cash Router address or not.
+/// checking if the receiver is a router in the registry or not. Calls whose input cannot be
+/// decoded are returned as errors.
 ///
 /// # Arguments
 /// * calls - a reference to a vector of references to ESNormalTransaction structs (which represent result entries obtained from Etherscan)
-pub fn split_deposit_withdraw(calls: &[&ESNormalTransaction]) -> (Vec<Deposit>, Vec<Withdraw>) {
-    calls.iter().fold((vec![], vec![]), |(dep, wit), c| {
-        if c.to.unwrap() == TORNADO_CASH_ROUTER.into() {
-            router_call(c, dep, wit)
-        } else {
-            pool_call(c, dep, wit)
+pub fn split_deposit_withdraw(calls: &[&ESNormalTransaction]) -> Decoded {
+    // a call is at most one deposit or withdraw
+    let mut decoded = (
+        Vec::with_capacity(calls.len()),
+        Vec::with_capacity(calls.len()),
+        vec![],
+    );
+
+    for c in calls {
+        match c.to {
+            Some(to) if registry().is_router(c.chain, to) => router_call(c, &mut decoded),
+            Some(to) => pool_call(c, to, &mut decoded),
+            // contract creations
+            None => {}
         }
-    })
+    }
+
+    decoded
+}
+
+fn pool_log(log: &PoolLog, (dep, wit, err): &mut Decoded) {
+    // skip logs of pending transactions and logs removed due to chain reorganizations
+    let (hash, block) = match (log.log.transaction_hash, log.log.block_number) {
+        (Some(h), Some(b)) if !log.log.is_removed() => (h, b.as_u64() as u128),
+        _ => return,
+    };
+
+    let event: PoolEvent = match (&log.log).try_into() {
+        Ok(e) => e,
+        Err(e) => {
+            err.push(decode_error(log.chain, hash, e));
+            return;
+        }
+    };
+
+    match event {
+        // the depositor is not part of the event, it can only be used if the sender is known
+        PoolEvent::Deposit(d) => {
+            if let Some(from) = log.from {
+                dep.push(
+                    Deposit::new(log.chain, hash, block, log.log.address, from)
+                        .with_timestamp(d._timestamp.low_u128()),
+                )
+            }
+        }
+        PoolEvent::Withdrawal(w) => wit.push(Withdraw::new(
+            log.chain,
+            hash,
+            block,
+            log.log.address,
+            w._to,
+            w._relayer,
+            w._fee,
+        )),
+        PoolEvent::Other => {}
+    }
+}
+
+/// Decode Deposit and Withdrawal events emitted by the pools. As opposed to
+/// [split_deposit_withdraw], this also finds deposits and withdraws made through other
+/// contracts. Deposit events for which the sender of the transaction is unknown are dropped, logs
+/// that cannot be decoded are returned as errors.
+///
+/// # Arguments
+/// * logs - a slice of references to PoolLog structs (which represent result entries of `eth_getLogs`)
+pub fn split_deposit_withdraw_logs(logs: &[&PoolLog]) -> Decoded {
+    // a log is at most one deposit or withdraw
+    let mut decoded = (
+        Vec::with_capacity(logs.len()),
+        Vec::with_capacity(logs.len()),
+        vec![],
+    );
+
+    for l in logs {
+        pool_log(l, &mut decoded);
+    }
+
+    decoded
+}
+
+/// Combine deposits and withdraws decoded from calls with those decoded from event logs. Logs
+/// contain every deposit/withdraw of a transaction, so for transactions found in the logs the
+/// decoded calls are dropped in order to not count them twice (transactions are identified by
+/// their chain and hash). Since logs do not contain the gas price (and Withdrawal events no
+/// timestamp), they are taken from the dropped calls.
+///
+/// # Arguments
+/// * calls - deposits and withdraws obtained with [split_deposit_withdraw]
+/// * logs - deposits and withdraws obtained with [split_deposit_withdraw_logs]
+pub fn merge_deposit_withdraw(
+    (call_deposits, call_withdraws): (Vec<Deposit>, Vec<Withdraw>),
+    (log_deposits, log_withdraws): (Vec<Deposit>, Vec<Withdraw>),
+) -> (Vec<Deposit>, Vec<Withdraw>) {
+    let hashes: HashSet<(u64, H256)> = log_deposits
+        .iter()
+        .map(|d| (d.chain, d.transaction_hash))
+        .chain(log_withdraws.iter().map(|w| (w.chain, w.transaction_hash)))
+        .collect();
+    let gas_prices: HashMap<(u64, H256), u128> = call_deposits
+        .iter()
+        .map(|d| ((d.chain, d.transaction_hash), d.gas_price))
+        .chain(
+            call_withdraws
+                .iter()
+                .map(|w| ((w.chain, w.transaction_hash), w.gas_price)),
+        )
+        .filter_map(|(h, g)| Some((h, g?)))
+        .collect();
+    let timestamps: HashMap<(u64, H256), u128> = call_deposits
+        .iter()
+        .map(|d| ((d.chain, d.transaction_hash), d.timestamp))
+        .chain(
+            call_withdraws
+                .iter()
+                .map(|w| ((w.chain, w.transaction_hash), w.timestamp)),
+        )
+        .filter_map(|(h, t)| Some((h, t?)))
+        .collect();
+
+    (
+        call_deposits
+            .into_iter()
+            .filter(|d| !hashes.contains(&(d.chain, d.transaction_hash)))
+            .chain(log_deposits.into_iter().map(|d| {
+                Deposit {
+                    gas_price: gas_prices.get(&(d.chain, d.transaction_hash)).copied(),
+                    timestamp: d
+                        .timestamp
+                        .or_else(|| timestamps.get(&(d.chain, d.transaction_hash)).copied()),
+                    ..d
+                }
+            }))
+            .collect(),
+        call_withdraws
+            .into_iter()
+            .filter(|w| !hashes.contains(&(w.chain, w.transaction_hash)))
+            .chain(log_withdraws.into_iter().map(|w| Withdraw {
+                gas_price: gas_prices.get(&(w.chain, w.transaction_hash)).copied(),
+                timestamp: timestamps.get(&(w.chain, w.transaction_hash)).copied(),
+                ..w
+            }))
+            .collect(),
+    )
 }
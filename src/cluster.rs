@@ -0,0 +1,228 @@
+use crate::data::{Deposit, Pool, Withdraw};
+use crate::hashstring;
+use crate::heuristics::Finding;
+use itertools::Itertools;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use web3::types::{H160, H256};
+
+// disjoint-set forest over addresses (union by size, path halving)
+struct UnionFind {
+    index: HashMap<H160, usize>,
+    parent: Vec<usize>,
+    size: Vec<usize>,
+}
+
+impl UnionFind {
+    fn new() -> Self {
+        UnionFind {
+            index: HashMap::new(),
+            parent: vec![],
+            size: vec![],
+        }
+    }
+
+    // index of an address, addresses not seen before form a set of their own
+    fn insert(&mut self, a: H160) -> usize {
+        let n = self.parent.len();
+        let i = *self.index.entry(a).or_insert(n);
+        if i == n {
+            self.parent.push(n);
+            self.size.push(1);
+        }
+        i
+    }
+
+    fn find(&mut self, mut i: usize) -> usize {
+        while self.parent[i] != i {
+            self.parent[i] = self.parent[self.parent[i]];
+            i = self.parent[i];
+        }
+        i
+    }
+
+    fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        if a != b {
+            let (large, small) = if self.size[a] >= self.size[b] {
+                (a, b)
+            } else {
+                (b, a)
+            };
+            self.parent[small] = large;
+            self.size[large] += self.size[small];
+        }
+    }
+}
+
+/// Evidence that links the addresses of a cluster: a finding of a heuristic
+#[derive(Serialize, Debug, Clone)]
+pub struct Edge {
+    pub heuristic: &'static str,
+    pub addresses: Vec<H160>,
+    /// Hashes of the deposits and withdraws of the finding
+    pub transactions: Vec<H256>,
+}
+
+/// Deposits and withdraws of the addresses of a cluster in a pool
+#[derive(Serialize, Debug, Clone)]
+pub struct PoolValue {
+    pub pool: Pool,
+    pub deposits: usize,
+    pub withdraws: usize,
+    /// Deposited amount of the pool's asset (0 if the denomination is unknown)
+    pub deposited: f64,
+    /// Withdrawn amount of the pool's asset (0 if the denomination is unknown)
+    pub withdrawn: f64,
+}
+
+/// A set of addresses that are (transitively) linked by the findings of the heuristics and thus
+/// likely belong to the same entity
+#[derive(Serialize, Debug, Clone)]
+pub struct Cluster {
+    /// Addresses of the cluster, sorted
+    pub addresses: Vec<H160>,
+    pub edges: Vec<Edge>,
+    /// Deposits and withdraws of the addresses, by pool
+    pub pools: Vec<PoolValue>,
+}
+
+impl Cluster {
+    /// One-line description of the cluster, printed with `--verbose`
+    pub fn describe(&self) -> String {
+        format!(
+            "{} addresses ({}) linked by {} findings ({}); {}",
+            self.addresses.len(),
+            self.addresses.iter().map(|a| hashstring!(a)).join(", "),
+            self.edges.len(),
+            self.edges.iter().map(|e| e.heuristic).unique().join(", "),
+            self.pools
+                .iter()
+                .map(|p| format!(
+                    "{}: {} deposits ({} {}), {} withdraws ({} {})",
+                    p.pool,
+                    p.deposits,
+                    p.deposited,
+                    p.pool.asset().unwrap_or_default(),
+                    p.withdraws,
+                    p.withdrawn,
+                    p.pool.asset().unwrap_or_default()
+                ))
+                .join(", ")
+        )
+    }
+}
+
+/// Merge the addresses linked by findings into clusters: two addresses are in the same cluster
+/// if they are linked by a finding or by a chain of findings (e.g., A and B by an address match
+/// and B and C by a deposit/withdraw pattern). Clusters are sorted by size (largest first).
+///
+/// # Arguments
+///
+/// * findings - the findings of any heuristics, each linking the addresses it contains
+/// * deposits - the deposits used to aggregate the deposited value of each cluster
+/// * withdraws - the withdraws used to aggregate the withdrawn value of each cluster
+pub fn cluster_findings(
+    findings: &[&Finding],
+    deposits: &[&Deposit],
+    withdraws: &[&Withdraw],
+) -> Vec<Cluster> {
+    let mut sets = UnionFind::new();
+    findings.iter().for_each(|f| {
+        let indices: Vec<usize> = f.addresses.iter().map(|a| sets.insert(*a)).collect();
+        indices
+            .iter()
+            .skip(1)
+            .for_each(|i| sets.union(indices[0], *i));
+    });
+
+    // addresses and edges by the root of their set
+    let mut members: HashMap<usize, Vec<H160>> = HashMap::new();
+    sets.index.clone().into_iter().for_each(|(a, i)| {
+        let root = sets.find(i);
+        members.entry(root).or_default().push(a);
+    });
+    let mut edges: HashMap<usize, Vec<Edge>> = HashMap::new();
+    findings
+        .iter()
+        .filter(|f| !f.addresses.is_empty())
+        .for_each(|f| {
+            let root = sets.find(sets.index[&f.addresses[0]]);
+            edges.entry(root).or_default().push(Edge {
+                heuristic: f.heuristic,
+                addresses: f.addresses.clone(),
+                transactions: f
+                    .deposits
+                    .iter()
+                    .chain(f.withdraws.iter())
+                    .map(|t| t.hash)
+                    .collect(),
+            });
+        });
+
+    // number of deposits/withdraws of each address by pool
+    let mut counts: HashMap<H160, BTreeMap<Pool, (usize, usize)>> = HashMap::new();
+    deposits
+        .iter()
+        .filter(|d| sets.index.contains_key(&d.from))
+        .for_each(|d| {
+            counts
+                .entry(d.from)
+                .or_default()
+                .entry(d.pool)
+                .or_default()
+                .0 += 1
+        });
+    withdraws
+        .iter()
+        .filter(|w| sets.index.contains_key(&w.receiver))
+        .for_each(|w| {
+            counts
+                .entry(w.receiver)
+                .or_default()
+                .entry(w.pool)
+                .or_default()
+                .1 += 1
+        });
+
+    members
+        .into_iter()
+        .map(|(root, addresses)| {
+            let pools = addresses
+                .iter()
+                .filter_map(|a| counts.get(a))
+                .fold(
+                    BTreeMap::new(),
+                    |mut m: BTreeMap<Pool, (usize, usize)>, c| {
+                        c.iter().for_each(|(p, (d, w))| {
+                            let e = m.entry(*p).or_default();
+                            e.0 += d;
+                            e.1 += w;
+                        });
+                        m
+                    },
+                )
+                .into_iter()
+                .map(|(pool, (d, w))| PoolValue {
+                    pool,
+                    deposits: d,
+                    withdraws: w,
+                    deposited: pool.amount(d as u64).unwrap_or_default(),
+                    withdrawn: pool.amount(w as u64).unwrap_or_default(),
+                })
+                .collect();
+
+            Cluster {
+                addresses: addresses.into_iter().sorted().collect(),
+                edges: edges.remove(&root).unwrap_or_default(),
+                pools,
+            }
+        })
+        .sorted_by(|a, b| {
+            b.addresses
+                .len()
+                .cmp(&a.addresses.len())
+                .then_with(|| a.addresses.cmp(&b.addresses))
+        })
+        .collect()
+}
@@ -1,50 +1,100 @@
+use super::registry::{registry, PoolDefinition, MAINNET};
 use super::{
-    Deposit, DirectDeposit, DirectWithdraw, ESNormalTransaction, ESNormalTransactionStrings,
-    ESTransaction, InBlock, InPool, Pool, PoolCall, RouterCall, RouterDeposit, RouterWithdraw,
-    Withdraw, DIRECT_DEPOSIT_SIGNATURE, DIRECT_WITHDRAW_SIGNATURE, ROUTER_DEPOSIT_SIGNATURE,
-    ROUTER_WITHDRAW_SIGNATURE, TORNADO_CASH_0_1ETH, TORNADO_CASH_100ETH, TORNADO_CASH_10ETH,
-    TORNADO_CASH_1ETH,
+    Deposit, DepositEvent, DirectWithdraw, ESNormalTransaction, ESNormalTransactionStrings,
+    InBlock, InChain, InPool, Pool, PoolCall, PoolEvent, RouterCall, RouterDeposit, RouterWithdraw,
+    Withdraw, WithdrawClass, WithdrawalEvent, AMOUNT_UNITS, DEPOSIT_EVENT_DATA,
+    DEPOSIT_EVENT_SIGNATURE, DIRECT_DEPOSIT_SIGNATURE, DIRECT_WITHDRAW_SIGNATURE,
+    ROUTER_DEPOSIT_SIGNATURE, ROUTER_WITHDRAW_SIGNATURE, WITHDRAWAL_EVENT_DATA,
+    WITHDRAWAL_EVENT_SIGNATURE,
 };
-use ethabi::{decode, short_signature, Token, Uint};
+use ethabi::{decode, long_signature, short_signature, Token, Uint};
 use hex::decode as hex_decode;
+use serde::{Serialize, Serializer};
 use std::error::Error;
-use web3::types::{H160, H256};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use web3::types::{Log, H160, H256};
 
 fn token_to_h160(token: &Token) -> Result<H160, Box<dyn Error>> {
-    let bytes: [u8; 20] = token.clone().into_address().unwrap()[..].try_into()?;
+    let bytes: [u8; 20] =
+        token.clone().into_address().ok_or("expected an address")?[..].try_into()?;
     Ok(bytes.into())
 }
 
-// used by Deposit::new(...) and Withdraw::new(...) to assign the Pool enum
-fn pool_by_addr(addr: H160) -> Pool {
-    let addr_bytes: [u8; 20] = addr[..].try_into().unwrap();
-    match addr_bytes {
-        TORNADO_CASH_0_1ETH => Pool::_0_1ETH,
-        TORNADO_CASH_1ETH => Pool::_1ETH,
-        TORNADO_CASH_10ETH => Pool::_10ETH,
-        TORNADO_CASH_100ETH => Pool::_100ETH,
-        _ => Pool::Unknown,
-    }
+fn token_to_uint(token: &Token) -> Result<Uint, Box<dyn Error>> {
+    Ok(token
+        .clone()
+        .into_uint()
+        .ok_or("expected an unsigned integer")?)
+}
+
+// Remove the "0x" prefix of a hexadecimal string
+fn strip_hex(s: &str) -> Result<&str, Box<dyn Error>> {
+    Ok(s.strip_prefix("0x")
+        .ok_or_else(|| format!("'{}' is not a hexadecimal string", s))?)
 }
 
-impl ESTransaction for ESNormalTransaction {
-    fn transaction_hash(&self) -> H256 {
-        self.hash
+impl Pool {
+    /// Handle for transactions to addresses that are not in the registry
+    pub const UNKNOWN: Pool = Pool(usize::MAX);
+
+    /// All pools of the installed registry
+    pub fn all() -> impl Iterator<Item = Pool> {
+        registry().all_pools()
+    }
+
+    /// Definition of the pool in the installed registry, `None` for [Pool::UNKNOWN]
+    pub fn definition(&self) -> Option<&'static PoolDefinition> {
+        registry().pools.get(self.0)
+    }
+
+    /// Address of the pool contract
+    pub fn address(&self) -> Option<H160> {
+        self.definition().map(|d| d.address)
+    }
+
+    /// Asset that is deposited to/withdrawn from the pool
+    pub fn asset(&self) -> Option<&'static str> {
+        self.definition().map(|d| d.asset.as_str())
+    }
+
+    /// ID of the chain the pool is deployed on
+    pub fn chain(&self) -> Option<u64> {
+        self.definition().map(|d| d.chain)
     }
-    fn transaction_value(&self) -> u128 {
-        self.value
+
+    /// Amount of the asset that is deposited/withdrawn with each call
+    pub fn denomination(&self) -> Option<f64> {
+        self.definition().map(|d| d.denomination)
     }
-    fn transaction_to(&self) -> Option<H160> {
-        self.to
+
+    /// Amount of the asset that is deposited/withdrawn with each call in billionths of the asset,
+    /// so that amounts can be added up without floating point errors
+    pub fn denomination_units(&self) -> Option<u128> {
+        self.denomination()
+            .map(|d| (d * AMOUNT_UNITS as f64).round() as u128)
     }
-    fn transaction_from(&self) -> H160 {
-        self.from
+
+    /// Amount of the asset that is deposited/withdrawn with `n` calls
+    pub fn amount(&self, n: u64) -> Option<f64> {
+        self.denomination_units()
+            .map(|u| (u * n as u128) as f64 / AMOUNT_UNITS as f64)
     }
-    fn transaction_blocknumber(&self) -> u128 {
-        self.blockNumber
+}
+
+impl Display for Pool {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self.definition() {
+            Some(d) if d.chain == MAINNET => write!(f, "{}", d.label),
+            Some(d) => write!(f, "{} ({})", d.label, registry().chain_name(d.chain)),
+            None => write!(f, "unknown"),
+        }
     }
-    fn transaction_is_error(&self) -> u128 {
-        self.isError
+}
+
+// pools are identified by their label (and chain) in serialized output
+impl Serialize for Pool {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
     }
 }
 
@@ -55,23 +105,23 @@ impl TryInto<ESNormalTransaction> for ESNormalTransactionStrings {
         Ok(ESNormalTransaction {
             blockNumber: self.blockNumber.parse()?,
             timeStamp: self.timeStamp.parse()?,
-            hash: self.hash.get(2..).unwrap().parse()?,
-            from: self.from.get(2..).unwrap().parse()?,
+            hash: strip_hex(&self.hash)?.parse()?,
+            from: strip_hex(&self.from)?.parse()?,
             to: if self.to.is_empty() {
                 None
             } else {
-                Some(self.to.get(2..).unwrap().parse()?)
+                Some(strip_hex(&self.to)?.parse()?)
             },
             value: self.value.parse()?,
             contractAddress: if self.contractAddress.is_empty() {
                 None
             } else {
-                Some(self.contractAddress.get(2..).unwrap().parse()?)
+                Some(strip_hex(&self.contractAddress)?.parse()?)
             },
             input: if self.input.is_empty() {
                 None
             } else {
-                Some(hex_decode(self.input.get(2..).unwrap())?)
+                Some(hex_decode(strip_hex(&self.input)?)?)
             },
             gas: self.gas.parse()?,
             gasUsed: self.gasUsed.parse()?,
@@ -83,39 +133,36 @@ impl TryInto<ESNormalTransaction> for ESNormalTransactionStrings {
             gasPrice: self.gasPrice.parse()?,
             transactionIndex: self.transactionIndex.parse()?,
             txreceipt_status: self.txreceipt_status.parse()?,
+            chain: MAINNET,
         })
     }
 }
 
-impl From<&[u8]> for RouterCall {
-    fn from(input: &[u8]) -> Self {
-        if input.len() >= 4
-            && input[0..4] == short_signature("withdraw", &ROUTER_WITHDRAW_SIGNATURE)
+impl TryInto<RouterCall> for &[u8] {
+    type Error = Box<dyn Error>;
+    fn try_into(self) -> Result<RouterCall, Box<dyn Error>> {
+        if self.len() >= 4 && self[0..4] == short_signature("withdraw", &ROUTER_WITHDRAW_SIGNATURE)
         {
-            RouterCall::Withdraw(input[4..].try_into().unwrap())
-        } else if input.len() >= 4
-            && input[0..4] == short_signature("deposit", &ROUTER_DEPOSIT_SIGNATURE)
+            Ok(RouterCall::Withdraw(self[4..].try_into()?))
+        } else if self.len() >= 4
+            && self[0..4] == short_signature("deposit", &ROUTER_DEPOSIT_SIGNATURE)
         {
-            RouterCall::Deposit(input[4..].try_into().unwrap())
+            Ok(RouterCall::Deposit(self[4..].try_into()?))
         } else {
-            RouterCall::Other
+            Ok(RouterCall::Other)
         }
     }
 }
 
 impl TryInto<RouterDeposit> for &[u8] {
-    type Error = ();
-    fn try_into(self) -> Result<RouterDeposit, ()> {
+    type Error = Box<dyn Error>;
+    fn try_into(self) -> Result<RouterDeposit, Box<dyn Error>> {
         if let Ok(v) = decode(&ROUTER_DEPOSIT_SIGNATURE, self) {
-            let pool_addr: [u8; 20] = v[0].clone().into_address().unwrap()[..].try_into().unwrap();
-
             Ok(RouterDeposit {
-                _tornado: pool_addr.into(),
-                _commitment: v[1].clone().into_fixed_bytes().unwrap(),
-                _encryptedNote: v[2].clone().into_bytes().unwrap(),
+                _tornado: token_to_h160(&v[0])?,
             })
         } else {
-            Err(())
+            Err("Could not decode input.".into())
         }
     }
 }
@@ -126,13 +173,9 @@ impl TryInto<RouterWithdraw> for &[u8] {
         if let Ok(v) = decode(&ROUTER_WITHDRAW_SIGNATURE, self) {
             Ok(RouterWithdraw {
                 _tornado: token_to_h160(&v[0])?,
-                _proof: v[1].clone().into_bytes().unwrap(),
-                _root: v[2].clone().into_fixed_bytes().unwrap(),
-                _nullifierHash: v[3].clone().into_fixed_bytes().unwrap(),
                 _recipient: token_to_h160(&v[4])?,
                 _relayer: token_to_h160(&v[5])?,
-                _fee: v[6].clone().into_uint().unwrap(),
-                _refund: v[7].clone().into_uint().unwrap(),
+                _fee: token_to_uint(&v[6])?,
             })
         } else {
             Err("Could not decode input.".into())
@@ -140,31 +183,21 @@ impl TryInto<RouterWithdraw> for &[u8] {
     }
 }
 
-impl From<&[u8]> for PoolCall {
-    fn from(input: &[u8]) -> Self {
-        if input.len() >= 4
-            && input[0..4] == short_signature("withdraw", &DIRECT_WITHDRAW_SIGNATURE)
+impl TryInto<PoolCall> for &[u8] {
+    type Error = Box<dyn Error>;
+    fn try_into(self) -> Result<PoolCall, Box<dyn Error>> {
+        if self.len() >= 4 && self[0..4] == short_signature("withdraw", &DIRECT_WITHDRAW_SIGNATURE)
         {
-            PoolCall::Withdraw(input[4..].try_into().unwrap())
-        } else if input.len() >= 4
-            && input[0..4] == short_signature("deposit", &DIRECT_DEPOSIT_SIGNATURE)
+            Ok(PoolCall::Withdraw(self[4..].try_into()?))
+        } else if self.len() >= 4
+            && self[0..4] == short_signature("deposit", &DIRECT_DEPOSIT_SIGNATURE)
         {
-            PoolCall::Deposit(input[4..].try_into().unwrap())
-        } else {
-            PoolCall::Other
-        }
-    }
-}
-
-impl TryInto<DirectDeposit> for &[u8] {
-    type Error = ();
-    fn try_into(self) -> Result<DirectDeposit, ()> {
-        if let Ok(v) = decode(&DIRECT_DEPOSIT_SIGNATURE, self) {
-            Ok(DirectDeposit {
-                _commitment: v[0].clone().into_fixed_bytes().unwrap(),
-            })
+            match decode(&DIRECT_DEPOSIT_SIGNATURE, &self[4..]) {
+                Ok(_) => Ok(PoolCall::Deposit),
+                Err(_) => Err("Could not decode input.".into()),
+            }
         } else {
-            Err(())
+            Ok(PoolCall::Other)
         }
     }
 }
@@ -174,13 +207,9 @@ impl TryInto<DirectWithdraw> for &[u8] {
     fn try_into(self) -> Result<DirectWithdraw, Box<dyn Error>> {
         if let Ok(v) = decode(&DIRECT_WITHDRAW_SIGNATURE, self) {
             Ok(DirectWithdraw {
-                _proof: v[0].clone().into_bytes().unwrap(),
-                _root: v[1].clone().into_fixed_bytes().unwrap(),
-                _nullifierHash: v[2].clone().into_fixed_bytes().unwrap(),
                 _recipient: token_to_h160(&v[3])?,
                 _relayer: token_to_h160(&v[4])?,
-                _fee: v[5].clone().into_uint().unwrap(),
-                _refund: v[6].clone().into_uint().unwrap(),
+                _fee: token_to_uint(&v[5])?,
             })
         } else {
             Err("Could not decode input.".into())
@@ -188,19 +217,108 @@ impl TryInto<DirectWithdraw> for &[u8] {
     }
 }
 
+impl PoolEvent {
+    /// First topic of logs emitted for the Deposit event
+    pub fn deposit_topic() -> H256 {
+        long_signature("Deposit", &DEPOSIT_EVENT_SIGNATURE).0.into()
+    }
+
+    /// First topic of logs emitted for the Withdrawal event
+    pub fn withdrawal_topic() -> H256 {
+        long_signature("Withdrawal", &WITHDRAWAL_EVENT_SIGNATURE)
+            .0
+            .into()
+    }
+}
+
+impl TryInto<PoolEvent> for &Log {
+    type Error = Box<dyn Error>;
+    fn try_into(self) -> Result<PoolEvent, Box<dyn Error>> {
+        match self.topics.first() {
+            Some(t) if *t == PoolEvent::deposit_topic() => Ok(PoolEvent::Deposit(self.try_into()?)),
+            Some(t) if *t == PoolEvent::withdrawal_topic() => {
+                Ok(PoolEvent::Withdrawal(self.try_into()?))
+            }
+            _ => Ok(PoolEvent::Other),
+        }
+    }
+}
+
+impl TryInto<DepositEvent> for &Log {
+    type Error = Box<dyn Error>;
+    fn try_into(self) -> Result<DepositEvent, Box<dyn Error>> {
+        // the commitment is indexed and hence stored in the second topic
+        if let (Some(_), Ok(v)) = (
+            self.topics.get(1),
+            decode(&DEPOSIT_EVENT_DATA, &self.data.0),
+        ) {
+            Ok(DepositEvent {
+                _timestamp: token_to_uint(&v[1])?,
+            })
+        } else {
+            Err("Could not decode log.".into())
+        }
+    }
+}
+
+impl TryInto<WithdrawalEvent> for &Log {
+    type Error = Box<dyn Error>;
+    fn try_into(self) -> Result<WithdrawalEvent, Box<dyn Error>> {
+        // the relayer is indexed and hence stored (left-padded) in the second topic
+        if let (Some(relayer), Ok(v)) = (
+            self.topics.get(1),
+            decode(&WITHDRAWAL_EVENT_DATA, &self.data.0),
+        ) {
+            Ok(WithdrawalEvent {
+                _to: token_to_h160(&v[0])?,
+                _relayer: H160::from_slice(&relayer[12..]),
+                _fee: token_to_uint(&v[2])?,
+            })
+        } else {
+            Err("Could not decode log.".into())
+        }
+    }
+}
+
 impl Deposit {
-    pub fn new(transaction_hash: H256, block_number: u128, pool_address: H160, from: H160) -> Self {
+    pub fn new(
+        chain: u64,
+        transaction_hash: H256,
+        block_number: u128,
+        pool_address: H160,
+        from: H160,
+    ) -> Self {
         Self {
+            chain,
             transaction_hash,
             block_number,
-            pool: pool_by_addr(pool_address),
+            pool: registry().pool_by_address(chain, pool_address),
             from,
+            gas_price: None,
+            timestamp: None,
+        }
+    }
+
+    /// Set the gas price of the deposit transaction
+    pub fn with_gas_price(self, gas_price: u128) -> Self {
+        Self {
+            gas_price: Some(gas_price),
+            ..self
+        }
+    }
+
+    /// Set the timestamp of the block containing the deposit transaction
+    pub fn with_timestamp(self, timestamp: u128) -> Self {
+        Self {
+            timestamp: Some(timestamp),
+            ..self
         }
     }
 }
 
 impl Withdraw {
     pub fn new(
+        chain: u64,
         transaction_hash: H256,
         block_number: u128,
         pool_address: H160,
@@ -209,36 +327,98 @@ impl Withdraw {
         fee: Uint,
     ) -> Self {
         Self {
+            chain,
             transaction_hash,
             block_number,
-            pool: pool_by_addr(pool_address),
+            pool: registry().pool_by_address(chain, pool_address),
             receiver,
             relayer,
             fee,
+            gas_price: None,
+            timestamp: None,
+        }
+    }
+
+    /// Set the gas price of the withdraw transaction
+    pub fn with_gas_price(self, gas_price: u128) -> Self {
+        Self {
+            gas_price: Some(gas_price),
+            ..self
+        }
+    }
+
+    /// Set the timestamp of the block containing the withdraw transaction
+    pub fn with_timestamp(self, timestamp: u128) -> Self {
+        Self {
+            timestamp: Some(timestamp),
+            ..self
+        }
+    }
+
+    /// How the withdraw was sent, judged by the relayer address only: without relayer (zero
+    /// address), by the receiver acting as its own relayer or by another relayer (whatever fee it
+    /// charged)
+    pub fn class(&self) -> WithdrawClass {
+        if self.relayer.is_zero() {
+            WithdrawClass::Direct
+        } else if self.relayer == self.receiver {
+            WithdrawClass::SelfRelayed
+        } else {
+            WithdrawClass::Relayed
         }
     }
 }
 
-impl<'a> InPool for Withdraw {
+impl Display for WithdrawClass {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            WithdrawClass::Relayed => write!(f, "relayed"),
+            WithdrawClass::Direct => write!(f, "direct"),
+            WithdrawClass::SelfRelayed => write!(f, "self_relayed"),
+        }
+    }
+}
+
+impl InPool for Withdraw {
     fn pool(&'_ self) -> &'_ Pool {
         &self.pool
     }
 }
 
-impl<'a> InPool for Deposit {
+impl InPool for Deposit {
     fn pool(&'_ self) -> &'_ Pool {
         &self.pool
     }
 }
 
+impl InChain for Deposit {
+    fn chain(&self) -> u64 {
+        self.chain
+    }
+}
+
+impl InChain for Withdraw {
+    fn chain(&self) -> u64 {
+        self.chain
+    }
+}
+
 impl InBlock for Deposit {
     fn block(&self) -> u128 {
         self.block_number
     }
+
+    fn timestamp(&self) -> Option<u128> {
+        self.timestamp
+    }
 }
 
 impl InBlock for Withdraw {
     fn block(&self) -> u128 {
         self.block_number
     }
+
+    fn timestamp(&self) -> Option<u128> {
+        self.timestamp
+    }
 }
@@ -1,24 +1,11 @@
 mod implementations;
+pub mod registry;
 
 use ethabi::{param_type::ParamType, Uint};
-use hex_literal::hex;
 use serde::{Deserialize, Serialize};
-use web3::types::{H160, H256};
-
-const TORNADO_CASH_0_1ETH: [u8; 20] = hex!("12D66f87A04A9E220743712cE6d9bB1B5616B8Fc");
-const TORNADO_CASH_1ETH: [u8; 20] = hex!("47CE0C6eD5B0Ce3d3A51fdb1C52DC66a7c3c2936");
-const TORNADO_CASH_10ETH: [u8; 20] = hex!("910Cbd523D972eb0a6f4cAe4618aD62622b39DbF");
-const TORNADO_CASH_100ETH: [u8; 20] = hex!("A160cdAB225685dA1d56aa342Ad8841c3b53f291");
-
-pub trait ESTransaction {
-    fn transaction_hash(&self) -> H256;
-    fn transaction_value(&self) -> u128;
-    fn transaction_from(&self) -> H160;
-    fn transaction_to(&self) -> Option<H160>;
-    fn transaction_blocknumber(&self) -> u128;
-    fn transaction_is_error(&self) -> u128;
-}
+use web3::types::{Log, H160, H256};
 
+/// A transaction as returned by the `txlist` action of the Etherscan API (all values are strings)
 #[allow(non_snake_case)]
 #[derive(Serialize, Deserialize, Debug)]
 pub struct ESNormalTransactionStrings {
@@ -42,6 +29,7 @@ pub struct ESNormalTransactionStrings {
     pub confirmations: String,
 }
 
+/// A parsed [ESNormalTransactionStrings]
 #[allow(non_snake_case)]
 #[derive(Debug, PartialEq, Hash, Eq, Clone)]
 pub struct ESNormalTransaction {
@@ -63,6 +51,8 @@ pub struct ESNormalTransaction {
     pub cumulativeGasUsed: u128,
     pub gasUsed: u128,
     pub confirmations: u128,
+    // not part of the Etherscan data, set when loading the transactions
+    pub chain: u64,
 }
 
 const DIRECT_WITHDRAW_SIGNATURE: [ParamType; 7] = [
@@ -94,6 +84,35 @@ const ROUTER_DEPOSIT_SIGNATURE: [ParamType; 3] = [
     ParamType::Bytes,
 ];
 
+// Deposit(bytes32 indexed commitment, uint32 leafIndex, uint256 timestamp)
+const DEPOSIT_EVENT_SIGNATURE: [ParamType; 3] = [
+    ParamType::FixedBytes(32),
+    ParamType::Uint(32),
+    ParamType::Uint(256),
+];
+
+// non-indexed parameters of the Deposit event, i.e., the ones contained in the log data
+const DEPOSIT_EVENT_DATA: [ParamType; 2] = [ParamType::Uint(32), ParamType::Uint(256)];
+
+// Withdrawal(address to, bytes32 nullifierHash, address indexed relayer, uint256 fee)
+const WITHDRAWAL_EVENT_SIGNATURE: [ParamType; 4] = [
+    ParamType::Address,
+    ParamType::FixedBytes(32),
+    ParamType::Address,
+    ParamType::Uint(256),
+];
+
+// non-indexed parameters of the Withdrawal event, i.e., the ones contained in the log data
+const WITHDRAWAL_EVENT_DATA: [ParamType; 3] = [
+    ParamType::Address,
+    ParamType::FixedBytes(32),
+    ParamType::Uint(256),
+];
+
+/// Number of units an amount of an asset is split into to add up amounts exactly (see
+/// [Pool::denomination_units])
+pub const AMOUNT_UNITS: u128 = 1_000_000_000;
+
 pub enum RouterCall {
     Withdraw(RouterWithdraw),
     Deposit(RouterDeposit),
@@ -102,76 +121,127 @@ pub enum RouterCall {
 
 pub enum PoolCall {
     Withdraw(DirectWithdraw),
-    Deposit(DirectDeposit),
+    Deposit,
     Other,
 }
 
-#[allow(non_snake_case)]
+pub enum PoolEvent {
+    Deposit(DepositEvent),
+    Withdrawal(WithdrawalEvent),
+    Other,
+}
+
+// only the parameters of the events (and calls below) that are needed for the analysis are kept
+pub struct DepositEvent {
+    pub _timestamp: Uint,
+}
+
+pub struct WithdrawalEvent {
+    pub _to: H160,
+    pub _relayer: H160,
+    pub _fee: Uint,
+}
+
+/// A log entry as returned by `eth_getLogs`. Since the Deposit event does not contain the
+/// depositing address, the sender of the transaction that emitted the log may be stored along
+/// with it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct PoolLog {
+    #[serde(flatten)]
+    pub log: Log,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub from: Option<H160>,
+    /// ID of the chain, not part of the `eth_getLogs` results but stored by
+    /// [fetch_logs](crate::fetch::fetch_logs) (missing in older files)
+    #[serde(default = "mainnet")]
+    pub chain: u64,
+}
+
+fn mainnet() -> u64 {
+    registry::MAINNET
+}
+
 pub struct DirectWithdraw {
-    pub _proof: Vec<u8>,
-    pub _root: Vec<u8>,
-    pub _nullifierHash: Vec<u8>,
     pub _recipient: H160,
     pub _relayer: H160,
     pub _fee: Uint,
-    pub _refund: Uint,
 }
 
-#[allow(non_snake_case)]
 pub struct RouterWithdraw {
     pub _tornado: H160,
-    pub _proof: Vec<u8>,
-    pub _root: Vec<u8>,
-    pub _nullifierHash: Vec<u8>,
     pub _recipient: H160,
     pub _relayer: H160,
     pub _fee: Uint,
-    pub _refund: Uint,
-}
-
-#[allow(non_snake_case)]
-pub struct DirectDeposit {
-    pub _commitment: Vec<u8>,
 }
 
-#[allow(non_snake_case)]
 pub struct RouterDeposit {
     pub _tornado: H160,
-    pub _commitment: Vec<u8>,
-    pub _encryptedNote: Vec<u8>,
 }
 
+/// A withdraw from a pool, decoded from a call or a Withdrawal event
 #[derive(Debug)]
 pub struct Withdraw {
+    /// ID of the chain the withdraw was made on
+    pub chain: u64,
     pub transaction_hash: H256,
     pub block_number: u128,
     pub pool: Pool,
+    /// Address that received the withdrawn funds
     pub receiver: H160,
+    /// Relayer that sent the transaction (zero address if the withdraw was not relayed)
     pub relayer: H160,
+    /// Fee paid to the relayer
     pub fee: Uint,
+    /// Gas price of the transaction (in wei), unknown for withdraws decoded from logs
+    pub gas_price: Option<u128>,
+    /// Timestamp of the block containing the transaction, unknown for withdraws decoded from logs
+    pub timestamp: Option<u128>,
 }
 
+/// How a withdraw was sent, see [Withdraw::class]
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+pub enum WithdrawClass {
+    /// Sent by a relayer other than the receiver (possibly without fee)
+    Relayed,
+    /// Sent without relayer, e.g., to collect TORN anonymity mining rewards
+    Direct,
+    /// The receiver was set as relayer, i.e., paid the fee to itself
+    SelfRelayed,
+}
+
+/// A deposit to a pool, decoded from a call or a Deposit event
 #[derive(Debug)]
 pub struct Deposit {
+    /// ID of the chain the deposit was made on
+    pub chain: u64,
     pub transaction_hash: H256,
     pub block_number: u128,
     pub pool: Pool,
+    /// Address that sent the deposit
     pub from: H160,
+    /// Gas price of the transaction (in wei), unknown for deposits decoded from logs
+    pub gas_price: Option<u128>,
+    /// Timestamp of the block containing the transaction
+    pub timestamp: Option<u128>,
 }
 
-#[derive(Debug)]
-pub enum Pool {
-    _0_1ETH,
-    _1ETH,
-    _10ETH,
-    _100ETH,
-    Unknown,
-}
+/// Handle of a pool in the [registry](registry::Registry)
+#[derive(Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
+pub struct Pool(usize);
 
+/// Deposits/withdraws that were sent to a pool
 pub trait InPool {
     fn pool(&'_ self) -> &'_ Pool;
 }
 
+/// Deposits/withdraws that were made on a chain
+pub trait InChain {
+    fn chain(&self) -> u64;
+}
+
+/// Deposits/withdraws that were included in a block
 pub trait InBlock {
     fn block(&self) -> u128;
+    /// Timestamp of the block (in seconds), if known
+    fn timestamp(&self) -> Option<u128>;
 }
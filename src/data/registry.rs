@@ -0,0 +1,195 @@
+use super::Pool;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::read_to_string;
+use std::path::Path;
+use std::sync::OnceLock;
+use web3::types::H160;
+
+// the built-in registry, also serves as an example for user-supplied files
+const DEFAULT_POOLS: &str = include_str!("default_pools.toml");
+
+static REGISTRY: OnceLock<Registry> = OnceLock::new();
+
+/// Chain ID of Ethereum mainnet
+pub const MAINNET: u64 = 1;
+
+/// Description of a Tornado Cash pool contract
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PoolDefinition {
+    /// ID of the chain the pool is deployed on (1 for Ethereum mainnet)
+    pub chain: u64,
+    pub address: H160,
+    /// Asset that is deposited to/withdrawn from the pool (e.g., "ETH" or "DAI")
+    pub asset: String,
+    /// Amount of the asset that is deposited/withdrawn with each call
+    pub denomination: f64,
+    /// Name used when printing results (e.g., "0.1 ETH")
+    pub label: String,
+}
+
+/// Description of a Tornado Cash router contract
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RouterDefinition {
+    /// ID of the chain the router is deployed on (1 for Ethereum mainnet)
+    pub chain: u64,
+    pub address: H160,
+    /// Name used when printing results
+    pub label: String,
+}
+
+/// Name of a chain, used when printing results
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChainDefinition {
+    pub id: u64,
+    pub name: String,
+}
+
+/// Set of known chains, pool and router contracts. [Pool] values are handles into the registry that is
+/// [installed](Registry::install) (or into the built-in registry, if none was installed).
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Registry {
+    #[serde(default)]
+    pub chains: Vec<ChainDefinition>,
+    #[serde(default)]
+    pub pools: Vec<PoolDefinition>,
+    #[serde(default)]
+    pub routers: Vec<RouterDefinition>,
+}
+
+impl Registry {
+    /// Registry containing the Tornado Cash contracts on Ethereum mainnet, BNB Smart Chain,
+    /// Polygon, Gnosis Chain, Arbitrum, Optimism and Avalanche.
+    pub fn builtin() -> Self {
+        toml::from_str(DEFAULT_POOLS).expect("built-in pool registry is invalid")
+    }
+
+    /// Load a registry from a TOML or JSON file (depending on the file extension, TOML is assumed
+    /// for files without `.json` extension).
+    pub fn from_file(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let contents = read_to_string(path)?;
+
+        match path.extension().and_then(|e| e.to_str()) {
+            Some("json") => Ok(serde_json::from_str(&contents)?),
+            _ => Ok(toml::from_str(&contents)?),
+        }
+    }
+
+    /// Add the chains, pools and routers of `other` to this registry. Entries of `other` replace
+    /// entries of this registry with the same chain (and address).
+    pub fn extend(mut self, other: Registry) -> Self {
+        other.chains.into_iter().for_each(|c| {
+            match self.chains.iter_mut().find(|d| d.id == c.id) {
+                Some(d) => *d = c,
+                None => self.chains.push(c),
+            }
+        });
+        other.pools.into_iter().for_each(|p| {
+            match self
+                .pools
+                .iter_mut()
+                .find(|q| q.chain == p.chain && q.address == p.address)
+            {
+                Some(q) => *q = p,
+                None => self.pools.push(p),
+            }
+        });
+        other.routers.into_iter().for_each(|r| {
+            match self
+                .routers
+                .iter_mut()
+                .find(|q| q.chain == r.chain && q.address == r.address)
+            {
+                Some(q) => *q = r,
+                None => self.routers.push(r),
+            }
+        });
+
+        self
+    }
+
+    /// Make this registry the one [Pool] handles refer to. This has to be done before any
+    /// transactions are loaded and can only be done once, otherwise the registry is returned.
+    pub fn install(self) -> Result<(), Registry> {
+        REGISTRY.set(self)
+    }
+
+    /// All pools of the registry
+    pub fn all_pools(&self) -> impl Iterator<Item = Pool> {
+        (0..self.pools.len()).map(Pool)
+    }
+
+    /// Handle of the pool with the given address on the given chain, [Pool::UNKNOWN] if there is
+    /// no such pool (the same address is used for different pools on different chains)
+    pub fn pool_by_address(&self, chain: u64, address: H160) -> Pool {
+        self.pools
+            .iter()
+            .position(|p| p.chain == chain && p.address == address)
+            .map(Pool)
+            .unwrap_or(Pool::UNKNOWN)
+    }
+
+    /// Check whether `address` belongs to a known router on the given chain
+    pub fn is_router(&self, chain: u64, address: H160) -> bool {
+        self.routers
+            .iter()
+            .any(|r| r.chain == chain && r.address == address)
+    }
+
+    /// Name of the chain with the given ID (or the ID itself if the chain is not known)
+    pub fn chain_name(&self, chain: u64) -> String {
+        self.chains
+            .iter()
+            .find(|c| c.id == chain)
+            .map(|c| c.name.clone())
+            .unwrap_or_else(|| format!("chain {}", chain))
+    }
+
+    /// Addresses of all known contracts on the given chain together with a name that can be used
+    /// as file name (e.g., "TORNADO_CASH_0_1ETH" for the pool labeled "0.1 ETH" on mainnet and
+    /// "TORNADO_CASH_42161_0_1ETH" for the one on Arbitrum)
+    pub fn contracts(&self, chain: u64) -> Vec<(String, H160)> {
+        self.pools
+            .iter()
+            .map(|p| (p.chain, &p.label, p.address))
+            .chain(self.routers.iter().map(|r| (r.chain, &r.label, r.address)))
+            .filter(|(c, _, _)| *c == chain)
+            .map(|(_, label, address)| {
+                (
+                    format!(
+                        "TORNADO_CASH_{}{}",
+                        if chain == MAINNET {
+                            String::new()
+                        } else {
+                            format!("{}_", chain)
+                        },
+                        label
+                            .chars()
+                            .filter_map(|c| match c {
+                                '.' => Some('_'),
+                                c if c.is_ascii_alphanumeric() => Some(c.to_ascii_uppercase()),
+                                _ => None,
+                            })
+                            .collect::<String>()
+                    ),
+                    address,
+                )
+            })
+            .collect()
+    }
+
+    /// ID of the chain of the contract with the given name (see [Registry::contracts]), i.e., the
+    /// chain a file written by [fetch_all](crate::fetch::fetch_all) belongs to
+    pub fn contract_chain(&self, name: &str) -> Option<u64> {
+        self.pools
+            .iter()
+            .map(|p| p.chain)
+            .chain(self.routers.iter().map(|r| r.chain))
+            .find(|c| self.contracts(*c).iter().any(|(n, _)| n == name))
+    }
+}
+
+/// The installed registry (see [Registry::install]), or the built-in one if none was installed.
+pub fn registry() -> &'static Registry {
+    REGISTRY.get_or_init(Registry::builtin)
+}
@@ -0,0 +1,149 @@
+use crate::data::registry::registry;
+use crate::hashstring;
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use web3::types::H256;
+
+/// Errors that can occur while loading and decoding transaction histories and event logs
+#[derive(Debug)]
+pub enum Error {
+    /// A file could not be read
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    /// A file does not contain a JSON array
+    Json {
+        path: String,
+        source: serde_json::Error,
+    },
+    /// An entry of a file could not be parsed, `index` is the position of the entry in the file
+    InvalidRow {
+        path: String,
+        index: usize,
+        hash: Option<String>,
+        reason: String,
+    },
+    /// A parsed transaction or log could not be decoded as deposit or withdraw, `entry` is the
+    /// path and the position of the transaction/log in its file if it was decoded while loading
+    Decode {
+        chain: u64,
+        hash: H256,
+        reason: String,
+        entry: Option<(String, usize)>,
+    },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
+        match self {
+            Error::Io { path, source } => write!(f, "could not read file '{}': {}", path, source),
+            Error::Json { path, source } => {
+                write!(f, "'{}' does not contain a JSON array: {}", path, source)
+            }
+            Error::InvalidRow {
+                path,
+                index,
+                hash,
+                reason,
+            } => match hash {
+                Some(h) => write!(f, "'{}', entry {} ({}): {}", path, index, h, reason),
+                None => write!(f, "'{}', entry {}: {}", path, index, reason),
+            },
+            Error::Decode {
+                chain,
+                hash,
+                reason,
+                entry,
+            } => {
+                if let Some((path, index)) = entry {
+                    write!(f, "'{}', entry {}: ", path, index)?;
+                }
+                write!(
+                    f,
+                    "transaction {} on {}: {}",
+                    hashstring!(hash),
+                    registry().chain_name(*chain),
+                    reason
+                )
+            }
+        }
+    }
+}
+
+impl Error {
+    /// Locate a decode error at an entry of a file, other errors are returned unchanged
+    ///
+    /// # Arguments
+    ///
+    /// * path - path of the file containing the undecodable transaction/log
+    /// * index - position of the transaction/log in the file
+    pub fn at(self, path: &str, index: usize) -> Self {
+        match self {
+            Error::Decode {
+                chain,
+                hash,
+                reason,
+                ..
+            } => Error::Decode {
+                chain,
+                hash,
+                reason,
+                entry: Some((path.to_string(), index)),
+            },
+            e => e,
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Json { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+/// Print a summary of the rows that were skipped because they could not be parsed or decoded
+/// to stderr.
+///
+/// # Arguments
+///
+/// * errors - the errors collected while loading and decoding
+/// * verbose - print every error instead of only the number of errors per file
+pub fn print_skipped(errors: &[Error], verbose: bool) {
+    if errors.is_empty() {
+        return;
+    }
+
+    eprintln!("skipped {} invalid rows", errors.len());
+    if verbose {
+        errors.iter().for_each(|e| eprintln!("  {}", e));
+    } else {
+        errors
+            .iter()
+            .fold(BTreeMap::new(), |mut counts, e| {
+                let source = match e {
+                    Error::Io { path, .. }
+                    | Error::Json { path, .. }
+                    | Error::InvalidRow { path, .. }
+                    | Error::Decode {
+                        entry: Some((path, _)),
+                        ..
+                    } => format!("'{}'", path),
+                    Error::Decode { chain, .. } => {
+                        format!(
+                            "undecodable calls/logs on {}",
+                            registry().chain_name(*chain)
+                        )
+                    }
+                };
+                *counts.entry(source).or_insert(0) += 1;
+                counts
+            })
+            .iter()
+            .for_each(|(source, n)| eprintln!("  {}: {}", source, n));
+    }
+}
@@ -0,0 +1,502 @@
+use crate::data::registry::{registry, MAINNET};
+use crate::data::{ESNormalTransactionStrings, PoolEvent, PoolLog};
+use crate::hashstring;
+use crate::helpers::parse_chain_path;
+use reqwest::blocking::Client;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{from_str, from_value, json, to_string, to_value, Value};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::fs::{read_to_string, rename, write};
+use std::path::Path;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use web3::types::{BlockNumber, FilterBuilder, Log, Transaction, H160, H256, U64};
+
+pub const ETHERSCAN_API_URL: &str = "https://api.etherscan.io/api";
+pub const DEFAULT_API_KEY: &str = "YourApiKeyToken";
+
+/// Block containing the deployment of the first Tornado Cash pool
+pub const DEFAULT_START_BLOCK: u128 = 9117609;
+
+/// Etherscan treats this as "the latest block"
+pub const DEFAULT_END_BLOCK: u128 = 99999999;
+
+/// Etherscan does not return more than 10000 transactions per request
+pub const DEFAULT_PAGE_SIZE: usize = 10000;
+
+// Etherscan allows one request every five seconds without an API key and five requests per
+// second with a free API key
+const DELAY_WITHOUT_KEY: Duration = Duration::from_millis(6000);
+const DELAY_WITH_KEY: Duration = Duration::from_millis(250);
+
+// how often a request is repeated if Etherscan reports that the rate limit was hit
+const MAX_RETRIES: u32 = 5;
+
+/// Many JSON-RPC providers refuse `eth_getLogs` requests spanning more blocks than this
+pub const DEFAULT_BLOCK_RANGE: u128 = 10000;
+
+#[derive(Deserialize, Debug)]
+struct EtherscanResponse {
+    status: String,
+    message: String,
+    result: Value,
+}
+
+/// Minimal blocking client for the `txlist` action of the Etherscan API.
+pub struct EtherscanClient {
+    client: Client,
+    api_url: String,
+    api_key: String,
+    chain: u64,
+    delay: Duration,
+    last_request: Option<Instant>,
+}
+
+impl EtherscanClient {
+    /// Create a new client.
+    ///
+    /// # Arguments
+    ///
+    /// * `api_url` - base URL of the API (e.g., [ETHERSCAN_API_URL] or a local mock server)
+    /// * `api_key` - Etherscan API key, [DEFAULT_API_KEY] works but is heavily rate limited
+    /// * `chain` - ID of the chain to query (passed as `chainid`, which is used by the Etherscan V2
+    ///   API and ignored by single-chain explorers)
+    /// * `delay` - minimum time between two requests, if `None` a delay suitable for the key is used
+    pub fn new(api_url: &str, api_key: &str, chain: u64, delay: Option<Duration>) -> Self {
+        Self {
+            client: Client::new(),
+            api_url: api_url.to_string(),
+            api_key: api_key.to_string(),
+            chain,
+            delay: delay.unwrap_or(if api_key == DEFAULT_API_KEY {
+                DELAY_WITHOUT_KEY
+            } else {
+                DELAY_WITH_KEY
+            }),
+            last_request: None,
+        }
+    }
+
+    // block until the configured delay since the last request has passed
+    fn wait(&mut self) {
+        if let Some(t) = self.last_request {
+            if let Some(remaining) = self.delay.checked_sub(t.elapsed()) {
+                sleep(remaining);
+            }
+        }
+        self.last_request = Some(Instant::now());
+    }
+
+    /// Get (at most `page_size`) transactions to or from `address` in the given block range,
+    /// sorted by block number.
+    pub fn txlist(
+        &mut self,
+        address: &str,
+        start_block: u128,
+        end_block: u128,
+        page_size: usize,
+    ) -> Result<Vec<ESNormalTransactionStrings>, Box<dyn Error>> {
+        for retry in 0..=MAX_RETRIES {
+            self.wait();
+
+            let response: EtherscanResponse = from_str(
+                &self
+                    .client
+                    .get(&self.api_url)
+                    .query(&[
+                        ("module", "account"),
+                        ("action", "txlist"),
+                        ("address", address),
+                        ("startblock", &start_block.to_string()),
+                        ("endblock", &end_block.to_string()),
+                        ("page", "1"),
+                        ("offset", &page_size.to_string()),
+                        ("sort", "asc"),
+                        ("chainid", &self.chain.to_string()),
+                        ("apikey", &self.api_key),
+                    ])
+                    .send()?
+                    .error_for_status()?
+                    .text()?,
+            )?;
+
+            if response.status == "1" {
+                return Ok(from_value(response.result)?);
+            } else if response.message.starts_with("No transactions found") {
+                return Ok(vec![]);
+            }
+
+            let reason = match response.result {
+                Value::String(s) => s,
+                other => other.to_string(),
+            };
+
+            if !reason.to_lowercase().contains("rate limit") || retry == MAX_RETRIES {
+                return Err(
+                    format!("Etherscan API error: {} ({})", response.message, reason).into(),
+                );
+            }
+
+            // back off before trying again
+            sleep(self.delay * 2u32.pow(retry + 1));
+        }
+
+        unreachable!()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct RpcError {
+    code: i64,
+    message: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct RpcResponse {
+    result: Option<Value>,
+    error: Option<RpcError>,
+}
+
+/// Minimal blocking Ethereum JSON-RPC client.
+pub struct RpcClient {
+    client: Client,
+    url: String,
+    id: u64,
+    chain: u64,
+}
+
+impl RpcClient {
+    /// Create a new client for the JSON-RPC endpoint at `url`. The ID of the chain is requested
+    /// from the endpoint.
+    pub fn new(url: &str) -> Result<Self, Box<dyn Error>> {
+        let mut client = Self {
+            client: Client::new(),
+            url: url.to_string(),
+            id: 0,
+            chain: MAINNET,
+        };
+        client.chain = client.chain_id()?;
+
+        Ok(client)
+    }
+
+    /// ID of the chain the endpoint belongs to
+    pub fn chain(&self) -> u64 {
+        self.chain
+    }
+
+    fn request<T: DeserializeOwned>(
+        &mut self,
+        method: &str,
+        params: Value,
+    ) -> Result<T, Box<dyn Error>> {
+        self.id += 1;
+
+        let response: RpcResponse = from_str(
+            &self
+                .client
+                .post(&self.url)
+                .header("Content-Type", "application/json")
+                .body(
+                    json!({"jsonrpc": "2.0", "id": self.id, "method": method, "params": params})
+                        .to_string(),
+                )
+                .send()?
+                .error_for_status()?
+                .text()?,
+        )?;
+
+        match (response.result, response.error) {
+            (_, Some(e)) => Err(format!("{} failed: {} ({})", method, e.message, e.code).into()),
+            (Some(r), None) => Ok(from_value(r)?),
+            (None, None) => Err(format!("{} returned no result", method).into()),
+        }
+    }
+
+    /// Get the ID of the chain.
+    pub fn chain_id(&mut self) -> Result<u64, Box<dyn Error>> {
+        let id: U64 = self.request("eth_chainId", json!([]))?;
+        Ok(id.as_u64())
+    }
+
+    /// Get the number of the latest block.
+    pub fn block_number(&mut self) -> Result<u128, Box<dyn Error>> {
+        let n: U64 = self.request("eth_blockNumber", json!([]))?;
+        Ok(n.as_u64().into())
+    }
+
+    /// Get all Deposit and Withdrawal logs emitted by `addresses` in the given block range.
+    pub fn pool_logs(
+        &mut self,
+        addresses: &[H160],
+        start_block: u128,
+        end_block: u128,
+    ) -> Result<Vec<Log>, Box<dyn Error>> {
+        let filter = FilterBuilder::default()
+            .address(addresses.to_vec())
+            .topics(
+                Some(vec![
+                    PoolEvent::deposit_topic(),
+                    PoolEvent::withdrawal_topic(),
+                ]),
+                None,
+                None,
+                None,
+            )
+            .from_block(BlockNumber::Number((start_block as u64).into()))
+            .to_block(BlockNumber::Number((end_block as u64).into()))
+            .build();
+
+        self.request("eth_getLogs", json!([to_value(filter)?]))
+    }
+
+    /// Get the address that sent the transaction with the given hash.
+    pub fn transaction_sender(&mut self, hash: H256) -> Result<H160, Box<dyn Error>> {
+        let t: Option<Transaction> = self.request("eth_getTransactionByHash", json!([hash]))?;
+
+        t.and_then(|t| t.from)
+            .ok_or_else(|| format!("transaction {} not found", hashstring!(hash)).into())
+    }
+}
+
+// write to a temporary file first so that an interrupted run never leaves a truncated file behind
+fn write_history<T: Serialize>(path: &Path, history: &[T]) -> Result<(), Box<dyn Error>> {
+    let tmp = path.with_extension("json.tmp");
+    write(&tmp, to_string(history)?)?;
+    rename(tmp, path)?;
+
+    Ok(())
+}
+
+/// Fetch the transaction history of `address` and store it in `path`. If `path` already exists,
+/// fetching resumes at the highest block number stored in the file and new transactions are
+/// appended. The chain is not stored with the transactions, if `path` is named after a contract on
+/// another chain than the one of `client` (see [parse_chain_path]), an error is returned. The file
+/// is written once at the end of the run, also if fetching fails, so a failed run can be resumed.
+/// Returns the number of transactions added to the file.
+///
+/// # Arguments
+///
+/// * `client` - client used to query the API
+/// * `path` - JSON file to store the history in (in the format read by [crate::helpers::load_files])
+/// * `address` - address whose history should be fetched
+/// * `start_block` - first block to fetch if there is no stored history yet
+/// * `end_block` - last block to fetch
+/// * `page_size` - number of transactions requested at once
+pub fn fetch_account(
+    client: &mut EtherscanClient,
+    path: &Path,
+    address: &str,
+    start_block: u128,
+    end_block: u128,
+    page_size: usize,
+) -> Result<usize, Box<dyn Error>> {
+    // the chain of the file is derived from its name when loading it
+    let (chain, _) = parse_chain_path(&path.to_string_lossy(), client.chain);
+    if chain != client.chain {
+        return Err(format!(
+            "'{}' belongs to {}, but the API is queried for {}",
+            path.display(),
+            registry().chain_name(chain),
+            registry().chain_name(client.chain)
+        )
+        .into());
+    }
+
+    let mut history: Vec<ESNormalTransactionStrings> = if path.exists() {
+        from_str(&read_to_string(path)?)?
+    } else {
+        vec![]
+    };
+    let stored = history.len();
+
+    // transactions of the block we resume at are fetched again, remember what we already have
+    let mut seen: HashSet<String> = history.iter().map(|t| t.hash.clone()).collect();
+
+    let mut block = history
+        .iter()
+        .map(|t| t.blockNumber.parse::<u128>())
+        .collect::<Result<Vec<u128>, _>>()?
+        .into_iter()
+        .max()
+        .unwrap_or(start_block);
+
+    let mut fetch = || -> Result<(), Box<dyn Error>> {
+        loop {
+            let page = client.txlist(address, block, end_block, page_size)?;
+            let full = page.len() >= page_size;
+            let last_block = page
+                .last()
+                .map(|t| t.blockNumber.parse::<u128>())
+                .transpose()?;
+
+            history.extend(page.into_iter().filter(|t| seen.insert(t.hash.clone())));
+
+            match last_block {
+                Some(b) if full && b < end_block => {
+                    // a single block with more transactions than fit on a page would loop forever
+                    if b == block {
+                        return Err(format!(
+                            "block {} contains more than {} transactions of {}",
+                            b, page_size, address
+                        )
+                        .into());
+                    }
+
+                    block = b;
+                }
+                _ => return Ok(()),
+            }
+        }
+    };
+    let fetched = fetch();
+
+    // keep what was fetched before an error
+    if history.len() > stored || !path.exists() {
+        write_history(path, &history)?;
+    }
+    fetched?;
+
+    Ok(history.len() - stored)
+}
+
+/// Fetch the transaction histories of all pools and routers in the
+/// [registry](crate::data::registry::Registry) on the chain of `client` into `<name>.json` files in `directory` (see
+/// [crate::data::registry::Registry::contracts] for the names).
+///
+/// # Arguments
+///
+/// * `client` - client used to query the API
+/// * `directory` - directory to store the files in
+/// * `start_block` - first block to fetch for accounts without stored history
+/// * `end_block` - last block to fetch
+/// * `page_size` - number of transactions requested at once
+pub fn fetch_all(
+    client: &mut EtherscanClient,
+    directory: &Path,
+    start_block: u128,
+    end_block: u128,
+    page_size: usize,
+) -> Result<(), Box<dyn Error>> {
+    for (name, addr) in registry().contracts(client.chain) {
+        let path = directory.join(format!("{}.json", name));
+        let added = fetch_account(
+            client,
+            &path,
+            &hashstring!(addr),
+            start_block,
+            end_block,
+            page_size,
+        )?;
+
+        println!("{}: added {} transactions", path.display(), added);
+    }
+
+    Ok(())
+}
+
+/// Fetch the Deposit and Withdrawal logs of `addresses` and store them in `path`. The sender of
+/// each transaction that emitted a Deposit event is looked up and stored with the log, since the
+/// event does not contain the depositor, as is the ID of the chain of `client`. Like
+/// [fetch_account], fetching resumes at the highest block number stored in `path` and the file is
+/// written once at the end of the run (also if fetching fails). If `path` contains logs of
+/// another chain, an error is returned. Returns the number of logs added to the file.
+///
+/// # Arguments
+///
+/// * `client` - client used to query the JSON-RPC endpoint
+/// * `path` - JSON file to store the logs in (in the format read by [crate::helpers::load_log_files])
+/// * `addresses` - pool addresses whose logs should be fetched
+/// * `start_block` - first block to fetch if there are no stored logs yet
+/// * `end_block` - last block to fetch
+/// * `block_range` - number of blocks requested at once
+pub fn fetch_logs(
+    client: &mut RpcClient,
+    path: &Path,
+    addresses: &[H160],
+    start_block: u128,
+    end_block: u128,
+    block_range: u128,
+) -> Result<usize, Box<dyn Error>> {
+    let mut logs: Vec<PoolLog> = if path.exists() {
+        from_str(&read_to_string(path)?)?
+    } else {
+        vec![]
+    };
+    let stored = logs.len();
+
+    // logs of different chains must not be mixed (logs of older files belong to mainnet)
+    if let Some(l) = logs.iter().find(|l| l.chain != client.chain) {
+        return Err(format!(
+            "'{}' contains logs of {}, but the endpoint belongs to {}",
+            path.display(),
+            registry().chain_name(l.chain),
+            registry().chain_name(client.chain)
+        )
+        .into());
+    }
+
+    // logs of the block we resume at are fetched again, remember what we already have
+    let mut seen: HashSet<(Option<H256>, Option<_>)> = logs
+        .iter()
+        .map(|l| (l.log.transaction_hash, l.log.log_index))
+        .collect();
+
+    // several deposits can be made in the same transaction
+    let mut senders: HashMap<H256, H160> = HashMap::new();
+    let deposit_topic = PoolEvent::deposit_topic();
+
+    let mut block = logs
+        .iter()
+        .filter_map(|l| l.log.block_number)
+        .map(|b| b.as_u64().into())
+        .max()
+        .unwrap_or(start_block);
+
+    let mut fetch = || -> Result<(), Box<dyn Error>> {
+        while block <= end_block {
+            let last = end_block.min(block + block_range.max(1) - 1);
+
+            for log in client.pool_logs(addresses, block, last)? {
+                if !seen.insert((log.transaction_hash, log.log_index)) {
+                    continue;
+                }
+
+                let from = match log.transaction_hash {
+                    Some(h) if log.topics.first() == Some(&deposit_topic) => {
+                        Some(match senders.get(&h) {
+                            Some(a) => *a,
+                            None => {
+                                let a = client.transaction_sender(h)?;
+                                senders.insert(h, a);
+                                a
+                            }
+                        })
+                    }
+                    _ => None,
+                };
+
+                logs.push(PoolLog {
+                    log,
+                    from,
+                    chain: client.chain,
+                });
+            }
+
+            block = last + 1;
+        }
+
+        Ok(())
+    };
+    let fetched = fetch();
+
+    // keep what was fetched before an error, the block we stopped at is fetched again
+    if logs.len() > stored || !path.exists() {
+        write_history(path, &logs)?;
+    }
+    fetched?;
+
+    Ok(logs.len() - stored)
+}
@@ -1,9 +1,19 @@
-use crate::data::{ESNormalTransaction, ESNormalTransactionStrings, InBlock, InPool, Pool};
-use itertools::Itertools;
-use serde::{Deserialize, Serialize};
-use serde_json::from_str;
-use std::fmt::Debug;
-use std::fs::read_to_string;
+use crate::data::registry::registry;
+use crate::data::{
+    Deposit, ESNormalTransaction, ESNormalTransactionStrings, InBlock, InChain, InPool, Pool,
+    PoolLog, Withdraw,
+};
+use crate::error::Error;
+use serde::de::{DeserializeOwned, Deserializer, Error as _, SeqAccess, Visitor};
+use serde_json::error::Category;
+use serde_json::{from_value, Deserializer as JsonDeserializer, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::File;
+use std::io::BufReader;
+use std::marker::PhantomData;
+use std::path::Path;
+use web3::types::{H256, U256};
 
 /// Turn a struct that implement AsBytes into a hexadecimal number
 #[macro_export]
@@ -19,105 +29,328 @@ macro_rules! hashstring {
     };
 }
 
-/// Add an element to a struct that implements IntoIter
-#[macro_export]
-macro_rules! immut_append {
-    ($l:expr, $a:expr) => {
-        $l.into_iter().chain([$a].into_iter()).collect()
+// an entry of a JSON array parsed into T, together with the chain ID stored with it
+type Row<T> = (T, Option<u64>);
+
+// Parse an entry of a JSON array into T, together with the chain ID stored with the entry (if
+// there is one). Entries that cannot be parsed are returned as errors (together with the path,
+// the position in the file and the hash if there is one).
+fn parse_row<T, U: TryInto<T> + DeserializeOwned>(
+    path: &str,
+    index: usize,
+    row: Value,
+) -> Result<Row<T>, Error>
+where
+    U::Error: Display,
+{
+    let hash = row
+        .get("hash")
+        .or_else(|| row.get("transactionHash"))
+        .and_then(Value::as_str)
+        .map(String::from);
+    let chain = row.get("chain").and_then(Value::as_u64);
+
+    from_value::<U>(row)
+        .map_err(|e| e.to_string())
+        .and_then(|u| u.try_into().map_err(|e| e.to_string()))
+        .map(|t| (t, chain))
+        .map_err(|reason| Error::InvalidRow {
+            path: path.to_string(),
+            index,
+            hash,
+            reason,
+        })
+}
+
+// Visits the entries of a JSON array one at a time, passing each parsed entry (and its position)
+// to `f`. If `f` fails, visiting stops and the error is kept in `failed`.
+struct Rows<'a, T, U> {
+    path: &'a str,
+    f: &'a mut dyn FnMut(usize, Result<Row<T>, Error>) -> Result<(), Error>,
+    failed: &'a mut Option<Error>,
+    row: PhantomData<U>,
+}
+
+impl<'de, 'a, T, U: TryInto<T> + DeserializeOwned> Visitor<'de> for Rows<'a, T, U>
+where
+    U::Error: Display,
+{
+    type Value = ();
+
+    fn expecting(&self, f: &mut Formatter) -> FmtResult {
+        write!(f, "a JSON array")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        let mut index = 0;
+        while let Some(row) = seq.next_element::<Value>()? {
+            if let Err(e) = (self.f)(index, parse_row::<T, U>(self.path, index, row)) {
+                *self.failed = Some(e);
+                return Err(A::Error::custom("aborted"));
+            }
+            index += 1;
+        }
+
+        Ok(())
+    }
+}
+
+// Generic function to parse a file containing a JSON array, used by stream_files(...) and
+// stream_log_files(...) to load transaction data. The file is read and the entries are parsed one
+// at a time, so only one entry is in memory at once. Every entry is passed to `f` together with
+// its position in the file, either parsed into T (together with the chain ID stored with it) or
+// as error. If `f` returns an error, the file is not read any further and the error is returned.
+fn stream_file<T, U: TryInto<T> + DeserializeOwned>(
+    path: &str,
+    f: &mut dyn FnMut(usize, Result<Row<T>, Error>) -> Result<(), Error>,
+) -> Result<(), Error>
+where
+    U::Error: Display,
+{
+    let file = File::open(path).map_err(|source| Error::Io {
+        path: path.to_string(),
+        source,
+    })?;
+    let mut de = JsonDeserializer::from_reader(BufReader::new(file));
+    let mut failed = None;
+
+    let res = de
+        .deserialize_seq(Rows {
+            path,
+            f,
+            failed: &mut failed,
+            row: PhantomData::<U>,
+        })
+        .and_then(|_| de.end());
+    if let Some(e) = failed {
+        return Err(e);
+    }
+
+    res.map_err(|source| match source.classify() {
+        Category::Io => Error::Io {
+            path: path.to_string(),
+            source: source.into(),
+        },
+        _ => Error::Json {
+            path: path.to_string(),
+            source,
+        },
+    })
+}
+
+/// Split a command line argument of the form `[CHAIN:]PATH` into the chain ID and the path. If
+/// there is no (numeric) prefix, the chain is derived from the file name if it is the name of a
+/// known contract (as written by [fetch_all](crate::fetch::fetch_all), e.g.,
+/// `TORNADO_CASH_56_1BNB.json`), otherwise `default_chain` is used. For event logs, the chain ID
+/// only applies to logs without a stored chain ID (see [fetch_logs](crate::fetch::fetch_logs)).
+pub fn parse_chain_path(arg: &str, default_chain: u64) -> (u64, &str) {
+    let (prefix, path) = match arg.split_once(':') {
+        Some((chain, path)) => match chain.parse() {
+            Ok(c) => (Some(c), path),
+            Err(_) => (None, arg),
+        },
+        None => (None, arg),
+    };
+
+    let named = || {
+        Path::new(path)
+            .file_stem()
+            .and_then(|n| n.to_str())
+            .and_then(|n| registry().contract_chain(n))
     };
+
+    (prefix.or_else(named).unwrap_or(default_chain), path)
 }
 
-// Generic function to parse &str into Vec<T>, used by load_files(...) to load transaction data
-fn parse_file<'a, T, U: TryInto<T> + Debug + Serialize + Deserialize<'a>>(
-    contents: &'a str,
-) -> Vec<T> {
-    from_str::<Vec<U>>(contents)
-        .unwrap()
-        .into_iter()
-        .map(|ess| ess.try_into().or(Err(())))
-        .map(|r| r.unwrap())
-        .collect()
+/// Stream transactions from JSON files obtained from Etherscan API: the transactions are parsed
+/// one at a time and passed to `f`, so the files are never loaded into memory as a whole.
+/// Transactions contained in multiple files (or multiple times in a file) are only passed once,
+/// for that the chain IDs and hashes of the passed transactions are kept. Transactions that cannot
+/// be parsed are skipped and returned as errors (unless `strict` is set), files that cannot be
+/// read are an error. Streaming stops at the first error returned by `f`.
+///
+/// # Arguments
+///
+/// * paths - chain IDs and strings describing file system paths, the transactions in a file are
+///   tagged with the chain ID (see [parse_chain_path])
+/// * filter - a filter function to select transactions with certain properties
+/// * strict - return the first transaction that cannot be parsed as error instead of skipping it
+/// * f - called with the path, the position in the file and the transaction for every selected
+///   transaction, in the order of the files
+pub fn stream_files(
+    paths: &[(u64, &str)],
+    filter: &dyn Fn(&ESNormalTransaction) -> bool,
+    strict: bool,
+    f: &mut dyn FnMut(&str, usize, ESNormalTransaction) -> Result<(), Error>,
+) -> Result<Vec<Error>, Error> {
+    let mut seen: HashSet<(u64, H256)> = HashSet::new();
+    let mut errors = vec![];
+
+    for (chain, p) in paths.iter().copied() {
+        stream_file::<ESNormalTransaction, ESNormalTransactionStrings>(p, &mut |i, r| match r {
+            Ok((t, _)) => {
+                let t = ESNormalTransaction { chain, ..t };
+                // get rid of duplicate entries
+                if filter(&t) && seen.insert((t.chain, t.hash)) {
+                    f(p, i, t)
+                } else {
+                    Ok(())
+                }
+            }
+            Err(e) if strict => Err(e),
+            Err(e) => {
+                errors.push(e);
+                Ok(())
+            }
+        })?;
+    }
+
+    Ok(errors)
 }
 
-/// Load transactions from JSON files obtained from Etherscan API.
+/// Load transactions from JSON files obtained from Etherscan API (see [stream_files]).
+/// Transactions that cannot be parsed are skipped and returned as errors, files that cannot be
+/// read are an error.
 ///
 /// # Arguments
 ///
-/// * paths - vector of strings describing file system paths
+/// * paths - vector of chain IDs and strings describing file system paths, the transactions in a
+///   file are tagged with the chain ID
 /// * filter - a filter function to select transactions with certain properties
 pub fn load_files(
-    paths: Vec<&str>,
+    paths: Vec<(u64, &str)>,
     filter: &dyn Fn(&ESNormalTransaction) -> bool,
-) -> Vec<ESNormalTransaction> {
-    paths
-        .into_iter()
-        .flat_map(|p| {
-            parse_file::<ESNormalTransaction, ESNormalTransactionStrings>(
-                &read_to_string(p).unwrap_or_else(|_| panic!("could not read file '{}'", p)),
-            )
-            .into_iter()
-        })
-        .filter(|t| filter(t))
-        // get rid of duplicate entries
-        .unique()
-        .collect()
+) -> Result<(Vec<ESNormalTransaction>, Vec<Error>), Error> {
+    let mut transactions = vec![];
+    let errors = stream_files(&paths, filter, false, &mut |_, _, t| {
+        transactions.push(t);
+        Ok(())
+    })?;
+
+    Ok((transactions, errors))
+}
+
+/// Stream Tornado Cash event logs from JSON files containing `eth_getLogs` results (e.g.,
+/// obtained with the `fetch-logs` subcommand), like [stream_files]: the logs are parsed one at a
+/// time and passed to `f`, logs contained in multiple files are only passed once (the chain IDs,
+/// transaction hashes and log indexes of the passed logs are kept). Logs that cannot be parsed
+/// are skipped and returned as errors (unless `strict` is set), files that cannot be read are an
+/// error. Streaming stops at the first error returned by `f`.
+///
+/// # Arguments
+///
+/// * paths - chain IDs and strings describing file system paths, the logs in a file are tagged
+///   with the chain ID unless a chain ID is stored with them (see
+///   [fetch_logs](crate::fetch::fetch_logs))
+/// * strict - return the first log that cannot be parsed as error instead of skipping it
+/// * f - called with the path, the position in the file and the log for every log, in the order
+///   of the files
+pub fn stream_log_files(
+    paths: &[(u64, &str)],
+    strict: bool,
+    f: &mut dyn FnMut(&str, usize, PoolLog) -> Result<(), Error>,
+) -> Result<Vec<Error>, Error> {
+    let mut seen: HashSet<(u64, Option<H256>, Option<U256>)> = HashSet::new();
+    let mut errors = vec![];
+
+    for (chain, p) in paths.iter().copied() {
+        stream_file::<PoolLog, PoolLog>(p, &mut |i, r| match r {
+            Ok((l, stored)) => {
+                let l = PoolLog {
+                    chain: stored.unwrap_or(chain),
+                    ..l
+                };
+                // get rid of duplicate entries
+                if seen.insert((l.chain, l.log.transaction_hash, l.log.log_index)) {
+                    f(p, i, l)
+                } else {
+                    Ok(())
+                }
+            }
+            Err(e) if strict => Err(e),
+            Err(e) => {
+                errors.push(e);
+                Ok(())
+            }
+        })?;
+    }
+
+    Ok(errors)
+}
+
+/// Load Tornado Cash event logs from JSON files containing `eth_getLogs` results (see
+/// [stream_log_files]). Logs that cannot be parsed are skipped and returned as errors, files that
+/// cannot be read are an error.
+///
+/// # Arguments
+///
+/// * paths - vector of chain IDs and strings describing file system paths, the logs in a file are
+///   tagged with the chain ID unless a chain ID is stored with them
+pub fn load_log_files(paths: Vec<(u64, &str)>) -> Result<(Vec<PoolLog>, Vec<Error>), Error> {
+    let mut logs = vec![];
+    let errors = stream_log_files(&paths, false, &mut |_, _, l| {
+        logs.push(l);
+        Ok(())
+    })?;
+
+    Ok((logs, errors))
 }
 
-/// Put withdraws/withdraws into separate vectors for each pool, i.e., return a 4-tuple with
-/// transactions for the 0.1 ETH pool, the 1 ETH pool, the 10 ETH pool and the 100 ETH pool
-/// respectively. Each vector is sorted by the number of the block containing the transaction.
+/// Put deposits/withdraws into separate vectors for each chain.
+///
+/// # Arguments
+///
+/// * ts - reference to slice of references to struct which implements InChain
+pub fn collect_chains<'a, T: InChain>(ts: &[&'a T]) -> BTreeMap<u64, Vec<&'a T>> {
+    ts.iter().copied().fold(BTreeMap::new(), |mut chains, t| {
+        chains.entry(t.chain()).or_insert_with(Vec::new).push(t);
+        chains
+    })
+}
+
+/// Put deposits/withdraws into separate vectors for each pool, keyed by pool (i.e., in the order
+/// of the [registry](crate::data::registry::Registry)). Each vector is sorted by the number of the
+/// block containing the transaction, transactions in the same block keep their order. Pools
+/// without transactions and transactions to unknown pools are not contained.
 ///
 /// # Arguments
 ///
 /// * ts - reference to slice of references to struct which implements InPool and InBlock
-///        (which holds for Deposits and Withdraws)
-pub fn collect_pools<'a, T: InPool + InBlock>(
-    ts: &[&'a T],
-) -> (Vec<&'a T>, Vec<&'a T>, Vec<&'a T>, Vec<&'a T>) {
-    ts.iter().copied().fold(
-        (vec![], vec![], vec![], vec![]),
-        |(_0_1eth, _1eth, _10eth, _100eth), t| match t.pool() {
-            Pool::_0_1ETH => (
-                _0_1eth
-                    .into_iter()
-                    .chain([t].into_iter())
-                    .sorted_by_key(|t| t.block())
-                    .collect(),
-                _1eth,
-                _10eth,
-                _100eth,
-            ),
-            Pool::_1ETH => (
-                _0_1eth,
-                _1eth
-                    .into_iter()
-                    .chain([t].into_iter())
-                    .sorted_by_key(|t| t.block())
-                    .collect(),
-                _10eth,
-                _100eth,
-            ),
-            Pool::_10ETH => (
-                _0_1eth,
-                _1eth,
-                _10eth
-                    .into_iter()
-                    .chain([t].into_iter())
-                    .sorted_by_key(|t| t.block())
-                    .collect(),
-                _100eth,
-            ),
-            Pool::_100ETH => (
-                _0_1eth,
-                _1eth,
-                _10eth,
-                _100eth
-                    .into_iter()
-                    .chain([t].into_iter())
-                    .sorted_by_key(|t| t.block())
-                    .collect(),
-            ),
-            Pool::Unknown => (_0_1eth, _1eth, _10eth, _100eth),
-        },
-    )
+///   (which holds for Deposits and Withdraws)
+pub fn collect_pools<'a, T: InPool + InBlock>(ts: &[&'a T]) -> BTreeMap<Pool, Vec<&'a T>> {
+    let mut pools = ts
+        .iter()
+        .copied()
+        .filter(|t| *t.pool() != Pool::UNKNOWN)
+        .fold(BTreeMap::new(), |mut pools, t| {
+            pools.entry(*t.pool()).or_insert_with(Vec::new).push(t);
+            pools
+        });
+    pools
+        .values_mut()
+        .for_each(|ts: &mut Vec<&T>| ts.sort_by_key(|t| t.block()));
+
+    pools
+}
+
+/// Put deposits and withdraws into separate vectors for each pool (see [collect_pools]), pairing
+/// the deposits and withdraws of a pool. Pools with deposits or withdraws are contained.
+///
+/// # Arguments
+///
+/// * deposits - reference to slice of references to deposits
+/// * withdraws - reference to slice of references to withdraws
+pub fn pair_pools<'a>(
+    deposits: &[&'a Deposit],
+    withdraws: &[&'a Withdraw],
+) -> BTreeMap<Pool, (Vec<&'a Deposit>, Vec<&'a Withdraw>)> {
+    let mut pools: BTreeMap<Pool, (Vec<&'a Deposit>, Vec<&'a Withdraw>)> = collect_pools(deposits)
+        .into_iter()
+        .map(|(p, ds)| (p, (ds, vec![])))
+        .collect();
+    collect_pools(withdraws)
+        .into_iter()
+        .for_each(|(p, ws)| pools.entry(p).or_default().1 = ws);
+
+    pools
 }
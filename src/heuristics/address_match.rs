@@ -0,0 +1,186 @@
+use super::{Finding, Group, Heuristic, Param, Params, Report};
+use crate::analysis::analyze::get_address_matches;
+use crate::data::{Deposit, ESNormalTransaction, Withdraw, WithdrawClass};
+use crate::hashstring;
+use crate::helpers::pair_pools;
+use itertools::Itertools;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+// number of blocks after which a withdraw only counts half as much (about a week on mainnet)
+const GAP_SCALE: f64 = 50000.0;
+
+/// Score of an address that deposited and withdrew: each withdraw halves the chance that the
+/// match is a coincidence (e.g., the address was reused by someone else), a long time between the
+/// deposits and the first withdraw makes it more likely (an unknown gap counts as infinite).
+///
+/// # Arguments
+///
+/// * withdraws - number of withdraws after a deposit
+/// * gap - minimum number of blocks between a withdraw and the latest deposit before it
+pub(crate) fn score(withdraws: usize, gap: Option<u128>) -> f64 {
+    let gap = gap.map_or(1.0, |g| g as f64 / (g as f64 + GAP_SCALE));
+
+    1.0 - 0.5f64.powi(withdraws as i32) * gap
+}
+
+/// Addresses that deposited to a pool and later withdrew from the same pool
+pub struct AddressMatch;
+
+impl Heuristic for AddressMatch {
+    fn name(&self) -> &'static str {
+        "address_match"
+    }
+
+    fn description(&self) -> &'static str {
+        "addresses that deposited to a pool and later withdrew from the same pool"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        vec![
+            Param {
+                name: "include_relayed",
+                description: "consider withdraws sent by a relayer",
+                default: "true",
+            },
+            Param {
+                name: "include_direct",
+                description: "consider withdraws without relayer (likely TORN anonymity mining)",
+                default: "true",
+            },
+            Param {
+                name: "include_self_relayed",
+                description: "consider withdraws whose receiver was set as relayer",
+                default: "true",
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        deposits: &[&Deposit],
+        withdraws: &[&Withdraw],
+        _history: &[&ESNormalTransaction],
+        params: &Params,
+    ) -> Result<Report, Box<dyn Error>> {
+        let mut classes = vec![];
+        if params.get("include_relayed")? {
+            classes.push(WithdrawClass::Relayed);
+        }
+        if params.get("include_direct")? {
+            classes.push(WithdrawClass::Direct);
+        }
+        if params.get("include_self_relayed")? {
+            classes.push(WithdrawClass::SelfRelayed);
+        }
+
+        // get deposits and withdraws by pool
+        // pools are analyzed in parallel, the groups keep the order of the pools
+        let groups = pair_pools(deposits, withdraws)
+            .into_par_iter()
+            .map(|(p, (d, w))| {
+                let w: Vec<&Withdraw> = w
+                    .into_iter()
+                    .filter(|w| classes.contains(&w.class()))
+                    .collect();
+                let res = get_address_matches(&d, &w);
+
+                Group {
+                    title: format!(
+                        "{} potentially compromised addresses in the {} pool (analysed {} deposits and {} withdraws)",
+                        res.len(),
+                        p,
+                        d.len(),
+                        w.len()
+                    ),
+                    findings: res
+                        .into_iter()
+                        .sorted_by_key(|(a, _)| *a)
+                        .map(|(a, (ds, ws))| {
+                            // blocks between a withdraw and the latest deposit before it (unknown
+                            // if there is no such deposit)
+                            let gap = ws
+                                .iter()
+                                .filter_map(|w| {
+                                    ds.iter()
+                                        .map(|d| d.block_number)
+                                        .filter(|b| *b < w.block_number)
+                                        .max()
+                                        .map(|b| w.block_number - b)
+                                })
+                                .min();
+                            let mut details = BTreeMap::from([(
+                                "classes",
+                                ws.iter().map(|w| w.class()).unique().sorted().join(","),
+                            )]);
+                            if let Some(gap) = gap {
+                                details.insert("blocks_since_deposit", gap.to_string());
+                            }
+                            // relayers that sent the withdraws (if any)
+                            let relayers = ws
+                                .iter()
+                                .filter(|w| w.class() != WithdrawClass::Direct)
+                                .map(|w| hashstring!(w.relayer))
+                                .unique()
+                                .join(",");
+                            if !relayers.is_empty() {
+                                details.insert("relayers", relayers);
+                            }
+                            // relayed withdraws without fee are unusual and hence telling
+                            let zero_fee = ws
+                                .iter()
+                                .filter(|w| w.class() == WithdrawClass::Relayed && w.fee.is_zero())
+                                .count();
+                            if zero_fee > 0 {
+                                details.insert("zero_fee_withdraws", zero_fee.to_string());
+                            }
+
+                            Finding {
+                                heuristic: self.name(),
+                                pools: vec![p],
+                                addresses: vec![a],
+                                score: score(ws.len(), gap),
+                                deposits: ds.into_iter().map(|d| d.into()).collect(),
+                                withdraws: ws.into_iter().map(|w| w.into()).collect(),
+                                pattern: None,
+                                details,
+                            }
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Ok(Report { groups })
+    }
+
+    fn describe(&self, finding: &Finding) -> String {
+        format!(
+            "{} deposited at {} and withdrew at {} ({}{})",
+            hashstring!(finding.addresses[0]),
+            finding
+                .deposits
+                .iter()
+                .map(|d| hashstring!(d.hash))
+                .collect::<Vec<String>>()
+                .join(", "),
+            finding
+                .withdraws
+                .iter()
+                .map(|w| hashstring!(w.hash))
+                .collect::<Vec<String>>()
+                .join(", "),
+            finding
+                .details
+                .get("classes")
+                .map(String::as_str)
+                .unwrap_or_default(),
+            finding
+                .details
+                .get("relayers")
+                .map(|r| format!(", relayers: {}", r))
+                .unwrap_or_default(),
+        )
+    }
+}
@@ -0,0 +1,94 @@
+use super::address_match::score;
+use super::{Finding, Group, Heuristic, Params, Report};
+use crate::analysis::analyze::get_cross_chain_address_matches;
+use crate::data::registry::registry;
+use crate::data::{Deposit, ESNormalTransaction, Withdraw};
+use crate::hashstring;
+use itertools::Itertools;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// Addresses that deposited on one chain and withdrew on another chain
+pub struct CrossChainAddressMatch;
+
+impl Heuristic for CrossChainAddressMatch {
+    fn name(&self) -> &'static str {
+        "cross_chain_address_match"
+    }
+
+    fn description(&self) -> &'static str {
+        "addresses that deposited on one chain and withdrew on another chain"
+    }
+
+    fn default_enabled(&self) -> bool {
+        false
+    }
+
+    fn run(
+        &self,
+        deposits: &[&Deposit],
+        withdraws: &[&Withdraw],
+        _history: &[&ESNormalTransaction],
+        _params: &Params,
+    ) -> Result<Report, Box<dyn Error>> {
+        let res = get_cross_chain_address_matches(deposits, withdraws);
+
+        Ok(Report {
+            groups: vec![Group {
+                title: format!(
+                    "{} addresses deposited and withdrew on different chains (analysed {} deposits and {} withdraws)",
+                    res.len(),
+                    deposits.len(),
+                    withdraws.len()
+                ),
+                findings: res
+                    .into_iter()
+                    .sorted_by_key(|(a, _)| *a)
+                    .map(|(a, (ds, ws))| Finding {
+                        heuristic: self.name(),
+                        pools: ds
+                            .iter()
+                            .map(|d| d.pool)
+                            .chain(ws.iter().map(|w| w.pool))
+                            .unique()
+                            .collect(),
+                        addresses: vec![a],
+                        // block numbers of different chains cannot be compared
+                        score: score(ws.len(), None),
+                        deposits: ds.into_iter().map(|d| d.into()).collect(),
+                        withdraws: ws.into_iter().map(|w| w.into()).collect(),
+                        pattern: None,
+                        details: BTreeMap::new(),
+                    })
+                    .collect(),
+            }],
+        })
+    }
+
+    fn describe(&self, finding: &Finding) -> String {
+        format!(
+            "{} deposited at {} and withdrew at {}",
+            hashstring!(finding.addresses[0]),
+            finding
+                .deposits
+                .iter()
+                .map(|d| format!(
+                    "{} ({})",
+                    hashstring!(d.hash),
+                    registry().chain_name(d.chain)
+                ))
+                .collect::<Vec<String>>()
+                .join(", "),
+            finding
+                .withdraws
+                .iter()
+                .map(|w| format!(
+                    "{} ({})",
+                    hashstring!(w.hash),
+                    registry().chain_name(w.chain)
+                ))
+                .collect::<Vec<String>>()
+                .join(", "),
+        )
+    }
+}
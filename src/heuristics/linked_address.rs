@@ -0,0 +1,132 @@
+use super::{Finding, Group, Heuristic, Param, Params, Report};
+use crate::analysis::analyze::get_linked_addresses;
+use crate::data::{Deposit, ESNormalTransaction, Withdraw};
+use crate::hashstring;
+use itertools::Itertools;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// Depositors and withdrawers that transacted with each other or share a funder outside of
+/// Tornado Cash
+pub struct LinkedAddress;
+
+impl Heuristic for LinkedAddress {
+    fn name(&self) -> &'static str {
+        "linked_address"
+    }
+
+    fn description(&self) -> &'static str {
+        "depositors and withdrawers that transacted with each other or were funded by the same address (requires --history)"
+    }
+
+    fn default_enabled(&self) -> bool {
+        false
+    }
+
+    fn params(&self) -> Vec<Param> {
+        vec![Param {
+            name: "max_funded",
+            description: "ignore funders that sent funds to more depositors/withdrawers than this (e.g., exchanges)",
+            default: "5",
+        }]
+    }
+
+    fn run(
+        &self,
+        deposits: &[&Deposit],
+        withdraws: &[&Withdraw],
+        history: &[&ESNormalTransaction],
+        params: &Params,
+    ) -> Result<Report, Box<dyn Error>> {
+        if history.is_empty() {
+            return Err(
+                "no transaction histories of depositors/withdrawers given (see --history)".into(),
+            );
+        }
+        let max_funded = params.get("max_funded")?;
+
+        let res = get_linked_addresses(deposits, withdraws, history, max_funded);
+
+        Ok(Report {
+            groups: vec![Group {
+                title: format!(
+                    "{} depositor/withdrawer pairs are linked outside of Tornado Cash (analysed {} deposits, {} withdraws and {} other transactions)",
+                    res.len(),
+                    deposits.len(),
+                    withdraws.len(),
+                    history.len()
+                ),
+                findings: res
+                    .into_iter()
+                    .map(|l| {
+                        // only the deposits and withdraws in the pools both addresses used
+                        let ds: Vec<&Deposit> =
+                            deposits.iter().filter(|d| d.from == l.depositor).copied().collect();
+                        let ws: Vec<&Withdraw> = withdraws
+                            .iter()
+                            .filter(|w| w.receiver == l.withdrawer)
+                            .filter(|w| ds.iter().any(|d| d.pool == w.pool))
+                            .copied()
+                            .collect();
+                        let ds: Vec<&Deposit> = ds
+                            .into_iter()
+                            .filter(|d| ws.iter().any(|w| w.pool == d.pool))
+                            .collect();
+
+                        let mut details = BTreeMap::from([(
+                            "transactions",
+                            l.transactions.iter().map(|t| hashstring!(t.hash)).join(","),
+                        )]);
+                        if !l.funders.is_empty() {
+                            details.insert(
+                                "funders",
+                                l.funders.iter().map(|a| hashstring!(a)).join(","),
+                            );
+                        }
+
+                        // transactions between depositor and withdrawer are stronger evidence than
+                        // a common funder
+                        let pair = [l.depositor, l.withdrawer];
+                        let direct = l
+                            .transactions
+                            .iter()
+                            .filter(|t| {
+                                pair.contains(&t.from) && t.to.is_some_and(|to| pair.contains(&to))
+                            })
+                            .count();
+
+                        Finding {
+                            heuristic: self.name(),
+                            pools: ds.iter().map(|d| d.pool).unique().sorted().collect(),
+                            addresses: vec![l.depositor, l.withdrawer],
+                            score: 1.0
+                                - 0.2f64.powi(direct as i32) * 0.5f64.powi(l.funders.len() as i32),
+                            deposits: ds.into_iter().map(|d| d.into()).collect(),
+                            withdraws: ws.into_iter().map(|w| w.into()).collect(),
+                            pattern: None,
+                            details,
+                        }
+                    })
+                    .collect(),
+            }],
+        })
+    }
+
+    fn describe(&self, finding: &Finding) -> String {
+        format!(
+            "{} deposited and {} withdrew, linked by {}{}",
+            hashstring!(finding.addresses[0]),
+            hashstring!(finding.addresses[1]),
+            finding
+                .details
+                .get("transactions")
+                .map(String::as_str)
+                .unwrap_or_default(),
+            finding
+                .details
+                .get("funders")
+                .map(|f| format!(" (common funders: {})", f))
+                .unwrap_or_default()
+        )
+    }
+}
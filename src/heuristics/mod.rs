@@ -0,0 +1,302 @@
+mod address_match;
+mod cross_chain_address_match;
+mod linked_address;
+mod multiple_denomination;
+mod timing;
+mod unique_gas_price;
+
+pub use address_match::AddressMatch;
+pub use cross_chain_address_match::CrossChainAddressMatch;
+pub use linked_address::LinkedAddress;
+pub use multiple_denomination::MultipleDenomination;
+pub use timing::Timing;
+pub use unique_gas_price::UniqueGasPrice;
+
+use crate::analysis::DepositWithdrawPattern;
+use crate::data::{Deposit, ESNormalTransaction, Pool, Withdraw};
+use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::str::FromStr;
+use web3::types::{H160, H256};
+
+/// A heuristic that links deposits and withdraws (or the addresses that made them). Heuristics
+/// are run in parallel, so they have to be `Send + Sync`.
+pub trait Heuristic: Send + Sync {
+    /// Name used to select the heuristic (e.g., "address_match")
+    fn name(&self) -> &'static str;
+
+    /// Short description of what the heuristic looks for
+    fn description(&self) -> &'static str;
+
+    /// Whether the heuristic is run if no heuristics are selected explicitly
+    fn default_enabled(&self) -> bool {
+        true
+    }
+
+    /// Parameters that can be set on the command line
+    fn params(&self) -> Vec<Param> {
+        vec![]
+    }
+
+    /// Apply the heuristic.
+    ///
+    /// # Arguments
+    ///
+    /// * deposits - the deposits to analyze
+    /// * withdraws - the withdraws to analyze
+    /// * history - further transactions of depositing/withdrawing addresses (may be empty)
+    /// * params - values of the [parameters](Heuristic::params) of the heuristic
+    fn run(
+        &self,
+        deposits: &[&Deposit],
+        withdraws: &[&Withdraw],
+        history: &[&ESNormalTransaction],
+        params: &Params,
+    ) -> Result<Report, Box<dyn Error>>;
+
+    /// One-line description of a finding of this heuristic, printed with `--verbose`
+    fn describe(&self, finding: &Finding) -> String;
+}
+
+/// All available heuristics, new heuristics have to be added here
+pub fn available_heuristics() -> Vec<Box<dyn Heuristic>> {
+    vec![
+        Box::new(AddressMatch),
+        Box::new(MultipleDenomination),
+        Box::new(CrossChainAddressMatch),
+        Box::new(UniqueGasPrice),
+        Box::new(Timing),
+        Box::new(LinkedAddress),
+    ]
+}
+
+/// A heuristic together with the values of its parameters
+pub type Selected = (Box<dyn Heuristic>, Params);
+
+/// Select heuristics by name (or all that are not opt-in if `names` is `None`) and set their
+/// parameters to the given values (see [Params::new]). Unknown heuristics and values for
+/// parameters of unknown or unselected heuristics are an error, invalid values are reported when
+/// a heuristic is [run](run_heuristics).
+///
+/// # Arguments
+///
+/// * names - names of the heuristics to select
+/// * values - values given by the user, mapping qualified names (e.g.,
+///   "multiple_denomination.min_pools") to values
+pub fn select_heuristics(
+    names: Option<&[&str]>,
+    values: &HashMap<String, String>,
+) -> Result<Vec<Selected>, String> {
+    let available = available_heuristics();
+
+    if let Some(unknown) = names
+        .unwrap_or_default()
+        .iter()
+        .find(|n| !available.iter().any(|h| h.name() == **n))
+    {
+        return Err(format!("unknown heuristic '{}' (see --list)", unknown));
+    }
+    if let Some(unknown) = values.keys().find(|k| {
+        !available
+            .iter()
+            .any(|h| k.starts_with(&format!("{}.", h.name())))
+    }) {
+        return Err(format!(
+            "'{}' is not a parameter of any heuristic (see --list)",
+            unknown
+        ));
+    }
+
+    available
+        .into_iter()
+        .filter(|h| match names {
+            Some(names) => names.contains(&h.name()),
+            None => h.default_enabled(),
+        })
+        .map(|h| Params::new(h.as_ref(), values).map(|params| (h, params)))
+        .collect()
+}
+
+/// Run the selected heuristics in parallel and return their reports in the order of the
+/// heuristics. If a heuristic fails, the error of the first failed heuristic in this order is
+/// returned.
+///
+/// # Arguments
+///
+/// * selected - the heuristics to run together with their parameters (see [select_heuristics])
+/// * deposits - the deposits to analyze
+/// * withdraws - the withdraws to analyze
+/// * history - further transactions of depositing/withdrawing addresses
+pub fn run_heuristics(
+    selected: &[Selected],
+    deposits: &[&Deposit],
+    withdraws: &[&Withdraw],
+    history: &[&ESNormalTransaction],
+) -> Result<Vec<Report>, Box<dyn Error>> {
+    let results: Vec<_> = selected
+        .par_iter()
+        .map(|(h, params)| {
+            h.run(deposits, withdraws, history, params)
+                // errors are boxed as `dyn Error`, which cannot be sent between threads
+                .map_err(|e| format!("{}: {}", h.name(), e))
+        })
+        .collect();
+
+    Ok(results.into_iter().collect::<Result<_, _>>()?)
+}
+
+/// A configurable parameter of a heuristic
+#[derive(Debug, Clone)]
+pub struct Param {
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Value used if the parameter is not set
+    pub default: &'static str,
+}
+
+/// Values of the parameters of a heuristic
+#[derive(Debug, Clone)]
+pub struct Params(HashMap<&'static str, String>);
+
+impl Params {
+    /// Values of the parameters of `heuristic`, taken from `values` (which maps qualified names,
+    /// e.g., "multiple_denomination.min_pools", to values) or set to the default. Values for
+    /// unknown parameters of the heuristic are an error.
+    ///
+    /// # Arguments
+    ///
+    /// * heuristic - the heuristic whose parameters are set
+    /// * values - values given by the user
+    pub fn new(
+        heuristic: &dyn Heuristic,
+        values: &HashMap<String, String>,
+    ) -> Result<Self, String> {
+        let params = heuristic.params();
+        let prefix = format!("{}.", heuristic.name());
+
+        if let Some(unknown) = values
+            .keys()
+            .filter_map(|k| k.strip_prefix(&prefix))
+            .find(|k| !params.iter().any(|p| p.name == *k))
+        {
+            return Err(format!(
+                "heuristic '{}' has no parameter '{}'",
+                heuristic.name(),
+                unknown
+            ));
+        }
+
+        Ok(Params(
+            params
+                .iter()
+                .map(|p| {
+                    (
+                        p.name,
+                        values
+                            .get(&format!("{}{}", prefix, p.name))
+                            .cloned()
+                            .unwrap_or_else(|| p.default.to_string()),
+                    )
+                })
+                .collect(),
+        ))
+    }
+
+    /// Value of a parameter, parsed into `T`
+    pub fn get<T: FromStr>(&self, name: &str) -> Result<T, Box<dyn Error>>
+    where
+        T::Err: Error + 'static,
+    {
+        let value = self
+            .0
+            .get(name)
+            .ok_or_else(|| format!("unknown parameter '{}'", name))?;
+
+        value.parse().map_err(|e| {
+            format!("invalid value '{}' for parameter '{}': {}", value, name, e).into()
+        })
+    }
+}
+
+/// A deposit or withdraw that is part of a finding
+#[derive(Serialize, Debug, Clone)]
+pub struct TransactionRecord {
+    pub chain: u64,
+    pub hash: H256,
+    pub block: u128,
+    pub pool: Pool,
+}
+
+impl From<&Deposit> for TransactionRecord {
+    fn from(d: &Deposit) -> Self {
+        TransactionRecord {
+            chain: d.chain,
+            hash: d.transaction_hash,
+            block: d.block_number,
+            pool: d.pool,
+        }
+    }
+}
+
+impl From<&Withdraw> for TransactionRecord {
+    fn from(w: &Withdraw) -> Self {
+        TransactionRecord {
+            chain: w.chain,
+            hash: w.transaction_hash,
+            block: w.block_number,
+            pool: w.pool,
+        }
+    }
+}
+
+/// A single result of a heuristic, e.g., an address that deposited to and withdrew from the same
+/// pool or two addresses with the same deposit/withdraw pattern
+#[derive(Serialize, Debug, Clone)]
+pub struct Finding {
+    /// Name of the heuristic that produced the finding
+    pub heuristic: &'static str,
+    pub pools: Vec<Pool>,
+    /// Addresses linked by the finding (depositing address first)
+    pub addresses: Vec<H160>,
+    /// Confidence that the addresses (or deposits and withdraws) are linked, between 0 and 1
+    /// (higher is more certain). Scores are computed differently by each heuristic.
+    pub score: f64,
+    pub deposits: Vec<TransactionRecord>,
+    pub withdraws: Vec<TransactionRecord>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pattern: Option<DepositWithdrawPattern>,
+    /// Further heuristic-specific information (e.g., the shared gas price)
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub details: BTreeMap<&'static str, String>,
+}
+
+/// Findings of a heuristic for a part of the data (e.g., a pool), together with a line
+/// summarizing them
+#[derive(Debug, Clone)]
+pub struct Group {
+    pub title: String,
+    pub findings: Vec<Finding>,
+}
+
+/// Result of running a heuristic
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub groups: Vec<Group>,
+}
+
+impl Report {
+    /// All findings of the report
+    pub fn findings(&self) -> impl Iterator<Item = &Finding> {
+        self.groups.iter().flat_map(|g| g.findings.iter())
+    }
+
+    /// Sort the findings of each group by score (most certain first), findings with the same
+    /// score keep their order
+    pub fn sort_by_score(&mut self) {
+        self.groups
+            .iter_mut()
+            .for_each(|g| g.findings.sort_by(|a, b| b.score.total_cmp(&a.score)));
+    }
+}
@@ -0,0 +1,264 @@
+use super::{Finding, Group, Heuristic, Param, Params, Report};
+use crate::analysis::analyze::{
+    match_patterns, match_patterns_in_windows, match_split_patterns, match_subset_patterns,
+};
+use crate::analysis::{PartialPatternMatch, PatternFrequencies, Unit};
+use crate::data::registry::registry;
+use crate::data::{Deposit, ESNormalTransaction, Withdraw};
+use crate::hashstring;
+use crate::helpers::collect_chains;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// Pairs of addresses with the same unique deposit/withdraw pattern (separately for each chain)
+pub struct MultipleDenomination;
+
+impl Heuristic for MultipleDenomination {
+    fn name(&self) -> &'static str {
+        "multiple_denomination"
+    }
+
+    fn description(&self) -> &'static str {
+        "pairs of addresses where one withdrew exactly the (unique) amounts the other deposited"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        vec![
+            Param {
+                name: "min_repeat",
+                description:
+                    "number of deposits to the same pool a pattern must contain at least once",
+                default: "2",
+            },
+            Param {
+                name: "min_pools",
+                description: "number of different pools a pattern must contain",
+                default: "2",
+            },
+            Param {
+                name: "mode",
+                description: "exact: equal deposit and withdraw patterns, subset: withdraw pattern is a part of the deposit pattern, split: two withdraw patterns add up to the deposit pattern",
+                default: "exact",
+            },
+            Param {
+                name: "max_candidates",
+                description: "maximum number of address combinations that explain a match (only subset and split)",
+                default: "1",
+            },
+            Param {
+                name: "window",
+                description: "maximum number of blocks (or seconds) spanned by the deposits (withdraws) of a pattern (0: all deposits/withdraws of an address, only exact)",
+                default: "0",
+            },
+            Param {
+                name: "max_delay",
+                description: "maximum number of blocks (or seconds) between the last deposit and the first withdraw of a pattern (only with window)",
+                default: "50000",
+            },
+            Param {
+                name: "unit",
+                description: "blocks or seconds (ignores deposits/withdraws with unknown timestamp), unit of window and max_delay",
+                default: "blocks",
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        deposits: &[&Deposit],
+        withdraws: &[&Withdraw],
+        _history: &[&ESNormalTransaction],
+        params: &Params,
+    ) -> Result<Report, Box<dyn Error>> {
+        let min_repeat = params.get("min_repeat")?;
+        let min_pools = params.get("min_pools")?;
+        let window: u128 = params.get("window")?;
+        let max_delay = params.get("max_delay")?;
+        let unit = Unit::parse(&params.get::<String>("unit")?)?;
+        let max_candidates = params.get("max_candidates")?;
+        let mode: String = params.get("mode")?;
+        let kind = match mode.as_str() {
+            "exact" => "unique deposit/withdraw patterns",
+            "subset" | "split" => "partial deposit/withdraw pattern matches",
+            _ => return Err(format!("unknown mode '{}'", mode).into()),
+        };
+        if window > 0 && mode != "exact" {
+            return Err(format!("window cannot be combined with mode '{}'", mode).into());
+        }
+        let deposits_by_chain = collect_chains(deposits);
+        let withdraws_by_chain = collect_chains(withdraws);
+
+        if deposits_by_chain.is_empty() {
+            return Ok(Report {
+                groups: vec![Group {
+                    title: format!("0 {} found", kind),
+                    findings: vec![],
+                }],
+            });
+        }
+
+        // chains are analyzed in parallel, the groups keep the order of the chains
+        let groups = deposits_by_chain
+            .par_iter()
+            .map(|(chain, ds)| {
+                let ws = withdraws_by_chain.get(chain).cloned().unwrap_or_default();
+                // a match of a pattern many depositors have is likely a coincidence
+                let frequencies = PatternFrequencies::from(&ds[..]);
+                // deposits of one address withdrawn by one or two other addresses
+                let partial = |matches: Vec<PartialPatternMatch>| -> Vec<Finding> {
+                    matches
+                        .into_iter()
+                        .map(|m| Finding {
+                            heuristic: self.name(),
+                            pools: m.pattern.counts.keys().copied().collect(),
+                            addresses: [m.depositor]
+                                .into_iter()
+                                .chain(m.withdrawers.iter().copied())
+                                .collect(),
+                            score: frequencies.score(&m.pattern) / m.candidates as f64,
+                            deposits: ds
+                                .iter()
+                                .filter(|d| d.from == m.depositor)
+                                .map(|d| (*d).into())
+                                .collect(),
+                            withdraws: ws
+                                .iter()
+                                .filter(|w| m.withdrawers.contains(&w.receiver))
+                                .map(|w| (*w).into())
+                                .collect(),
+                            details: BTreeMap::from([
+                                ("candidates", m.candidates.to_string()),
+                                (
+                                    "withdrawn",
+                                    m.withdraw_patterns
+                                        .iter()
+                                        .map(|p| p.to_string())
+                                        .collect::<Vec<String>>()
+                                        .join(" | "),
+                                ),
+                            ]),
+                            pattern: Some(m.pattern),
+                        })
+                        .collect()
+                };
+                let res: Vec<Finding> = if mode == "subset" {
+                    partial(match_subset_patterns(
+                        ds,
+                        &ws,
+                        min_repeat,
+                        min_pools,
+                        max_candidates,
+                    ))
+                } else if mode == "split" {
+                    partial(match_split_patterns(
+                        ds,
+                        &ws,
+                        min_repeat,
+                        min_pools,
+                        max_candidates,
+                    ))
+                } else if window == 0 {
+                    match_patterns(ds, &ws, min_repeat, min_pools)
+                        .into_iter()
+                        .map(|(a, b, p)| Finding {
+                            heuristic: self.name(),
+                            pools: p.counts.keys().copied().collect(),
+                            addresses: vec![a, b],
+                            score: frequencies.score(&p),
+                            deposits: ds
+                                .iter()
+                                .filter(|d| d.from == a)
+                                .map(|d| (*d).into())
+                                .collect(),
+                            withdraws: ws
+                                .iter()
+                                .filter(|w| w.receiver == b)
+                                .map(|w| (*w).into())
+                                .collect(),
+                            pattern: Some(p),
+                            details: BTreeMap::new(),
+                        })
+                        .collect()
+                } else {
+                    // only the deposits/withdraws of the matching windows
+                    match_patterns_in_windows(
+                        ds, &ws, min_repeat, min_pools, window, max_delay, unit,
+                    )
+                    .into_iter()
+                    .map(|m| Finding {
+                        heuristic: self.name(),
+                        pools: m.pattern.counts.keys().copied().collect(),
+                        addresses: vec![m.depositor, m.withdrawer],
+                        score: frequencies.score(&m.pattern),
+                        // in blocks or seconds, see unit
+                        details: BTreeMap::from([(
+                            "delay",
+                            (unit.position(m.withdraws[0]).unwrap_or_default()
+                                - unit
+                                    .position(m.deposits[m.deposits.len() - 1])
+                                    .unwrap_or_default())
+                            .to_string(),
+                        )]),
+                        deposits: m.deposits.into_iter().map(|d| d.into()).collect(),
+                        withdraws: m.withdraws.into_iter().map(|w| w.into()).collect(),
+                        pattern: Some(m.pattern),
+                    })
+                    .collect()
+                };
+
+                Group {
+                    // only mention the chain if data of multiple chains was loaded
+                    title: if deposits_by_chain.len() > 1 {
+                        format!(
+                            "{} {} found on {}",
+                            res.len(),
+                            kind,
+                            registry().chain_name(*chain)
+                        )
+                    } else {
+                        format!("{} {} found", res.len(), kind)
+                    },
+                    findings: res,
+                }
+            })
+            .collect();
+
+        Ok(Report { groups })
+    }
+
+    fn describe(&self, finding: &Finding) -> String {
+        match finding.details.get("withdrawn") {
+            Some(withdrawn) => format!(
+                "{} deposited {}, {} withdrew {} ({} candidates)",
+                hashstring!(finding.addresses[0]),
+                finding
+                    .pattern
+                    .as_ref()
+                    .map(|p| p.to_string())
+                    .unwrap_or_default(),
+                finding.addresses[1..]
+                    .iter()
+                    .map(|a| hashstring!(a))
+                    .collect::<Vec<String>>()
+                    .join(" and "),
+                withdrawn,
+                finding
+                    .details
+                    .get("candidates")
+                    .map(String::as_str)
+                    .unwrap_or_default()
+            ),
+            None => format!(
+                "{} and {} have the same deposit/withdraw pattern ({})",
+                hashstring!(finding.addresses[0]),
+                hashstring!(finding.addresses[1]),
+                finding
+                    .pattern
+                    .as_ref()
+                    .map(|p| p.to_string())
+                    .unwrap_or_default()
+            ),
+        }
+    }
+}
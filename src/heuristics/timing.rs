@@ -0,0 +1,159 @@
+use super::{Finding, Group, Heuristic, Param, Params, Report};
+use crate::analysis::analyze::get_timing_matches;
+use crate::analysis::Unit;
+use crate::data::{Deposit, ESNormalTransaction, Withdraw};
+use crate::hashstring;
+use crate::helpers::pair_pools;
+use itertools::Itertools;
+use rayon::prelude::*;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// Score of a withdraw with few candidate deposits: one over the number of candidate addresses,
+/// reduced (logarithmically) by the number of older deposits outside of the window that could
+/// have funded the withdraw as well
+///
+/// # Arguments
+///
+/// * addresses - number of addresses that made the candidate deposits
+/// * candidates - number of candidate deposits
+/// * anonymity_set - number of deposits to the pool before the withdraw
+fn score(addresses: usize, candidates: usize, anonymity_set: usize) -> f64 {
+    let older = anonymity_set.max(candidates) as f64 / candidates.max(1) as f64;
+
+    1.0 / (addresses as f64 * (1.0 + older.ln()))
+}
+
+/// Withdraws that can only have been funded by deposits of very few addresses shortly before
+pub struct Timing;
+
+impl Heuristic for Timing {
+    fn name(&self) -> &'static str {
+        "timing"
+    }
+
+    fn description(&self) -> &'static str {
+        "withdraws for which only deposits of very few addresses were made to the pool shortly before"
+    }
+
+    fn default_enabled(&self) -> bool {
+        false
+    }
+
+    fn params(&self) -> Vec<Param> {
+        vec![
+            Param {
+                name: "window",
+                description:
+                    "maximum number of blocks (or seconds) between a deposit and the withdraw it funded",
+                default: "300",
+            },
+            Param {
+                name: "unit",
+                description: "blocks or seconds (ignores deposits/withdraws with unknown timestamp), unit of window",
+                default: "blocks",
+            },
+            Param {
+                name: "max_candidates",
+                description: "maximum number of addresses that made the deposits within the window",
+                default: "1",
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        deposits: &[&Deposit],
+        withdraws: &[&Withdraw],
+        _history: &[&ESNormalTransaction],
+        params: &Params,
+    ) -> Result<Report, Box<dyn Error>> {
+        let window = params.get("window")?;
+        let max_candidates = params.get("max_candidates")?;
+        let unit = Unit::parse(&params.get::<String>("unit")?)?;
+
+        // pools are analyzed in parallel, the groups keep the order of the pools
+        let groups = pair_pools(deposits, withdraws)
+            .into_par_iter()
+            .map(|(p, (d, w))| {
+                let res = get_timing_matches(&d, &w, window, max_candidates, unit);
+                // number of deposits to the pool before a withdraw
+                let anonymity_set =
+                    |w: &Withdraw| d.partition_point(|d| d.block_number < w.block_number);
+
+                Group {
+                    title: format!(
+                        "{} withdraws with few candidate deposits in the {} pool (analysed {} deposits and {} withdraws)",
+                        res.len(),
+                        p,
+                        d.len(),
+                        w.len()
+                    ),
+                    findings: res
+                        .into_iter()
+                        .map(|(w, ds)| {
+                            let addresses: Vec<_> = ds.iter().map(|d| d.from).unique().collect();
+
+                            Finding {
+                                heuristic: self.name(),
+                                pools: vec![p],
+                                // candidate depositors first, the receiver last
+                                addresses: addresses
+                                    .iter()
+                                    .copied()
+                                    .chain([w.receiver])
+                                    .collect(),
+                                score: score(addresses.len(), ds.len(), anonymity_set(w)),
+                                deposits: ds.iter().map(|d| (*d).into()).collect(),
+                                withdraws: vec![w.into()],
+                                pattern: None,
+                                details: BTreeMap::from([
+                                    ("candidate_deposits", ds.len().to_string()),
+                                    ("candidate_addresses", addresses.len().to_string()),
+                                    (
+                                        match unit {
+                                            Unit::Blocks => "blocks_since_deposit",
+                                            Unit::Seconds => "seconds_since_deposit",
+                                        },
+                                        ds.iter()
+                                            .filter_map(|d| {
+                                                Some(unit.position(w)? - unit.position(*d)?)
+                                            })
+                                            .min()
+                                            .unwrap_or_default()
+                                            .to_string(),
+                                    ),
+                                ]),
+                            }
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        Ok(Report { groups })
+    }
+
+    fn describe(&self, finding: &Finding) -> String {
+        // candidate depositors first, the receiver last
+        let (receiver, depositors) = finding.addresses.split_last().unwrap();
+
+        format!(
+            "{} withdrew at {}, {} candidate deposits by {} ({})",
+            hashstring!(receiver),
+            hashstring!(finding.withdraws[0].hash),
+            finding.deposits.len(),
+            depositors
+                .iter()
+                .map(|a| hashstring!(a))
+                .collect::<Vec<String>>()
+                .join(", "),
+            finding
+                .deposits
+                .iter()
+                .map(|d| hashstring!(d.hash))
+                .collect::<Vec<String>>()
+                .join(", "),
+        )
+    }
+}
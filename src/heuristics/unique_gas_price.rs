@@ -0,0 +1,119 @@
+use super::{Finding, Group, Heuristic, Param, Params, Report};
+use crate::analysis::analyze::get_gas_price_matches;
+use crate::data::{Deposit, ESNormalTransaction, Withdraw, WithdrawClass};
+use crate::hashstring;
+use itertools::Itertools;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// Deposits and withdraws sent with the same unusual gas price
+pub struct UniqueGasPrice;
+
+impl Heuristic for UniqueGasPrice {
+    fn name(&self) -> &'static str {
+        "unique_gas_price"
+    }
+
+    fn description(&self) -> &'static str {
+        "deposits and withdraws without relayer sent with the same unusual gas price (mostly before EIP-1559)"
+    }
+
+    fn default_enabled(&self) -> bool {
+        false
+    }
+
+    fn params(&self) -> Vec<Param> {
+        vec![
+            Param {
+                name: "round_gwei",
+                description: "gas prices that are a multiple of this amount of gwei are considered common (0: none)",
+                default: "1",
+            },
+            Param {
+                name: "max_uses",
+                description: "maximum number of deposits (and withdraws) sharing a gas price",
+                default: "1",
+            },
+            Param {
+                name: "include_relayed",
+                description: "also consider withdraws sent by a relayer (which may use a gas price chosen by the user)",
+                default: "false",
+            },
+        ]
+    }
+
+    fn run(
+        &self,
+        deposits: &[&Deposit],
+        withdraws: &[&Withdraw],
+        _history: &[&ESNormalTransaction],
+        params: &Params,
+    ) -> Result<Report, Box<dyn Error>> {
+        let round = (params.get::<f64>("round_gwei")? * 1e9).round() as u128;
+        let max_uses = params.get("max_uses")?;
+        let include_relayed: bool = params.get("include_relayed")?;
+
+        // the gas price of a relayed withdraw is usually chosen by the relayer, not by the user
+        let ds: Vec<&Deposit> = deposits
+            .iter()
+            .filter(|d| d.gas_price.is_some())
+            .copied()
+            .collect();
+        let ws: Vec<&Withdraw> = withdraws
+            .iter()
+            .filter(|w| w.gas_price.is_some())
+            .filter(|w| include_relayed || w.class() != WithdrawClass::Relayed)
+            .copied()
+            .collect();
+        // a depositor withdrawing to itself is already found by address_match
+        let res: Vec<_> = get_gas_price_matches(&ds, &ws, round, max_uses)
+            .into_iter()
+            .filter(|(d, w)| d.from != w.receiver)
+            .collect();
+        // number of pairs sharing each gas price, a pair is one of that many candidates
+        let pairs = res.iter().map(|(d, _)| (d.chain, d.gas_price)).counts();
+
+        Ok(Report {
+            groups: vec![Group {
+                title: format!(
+                    "{} deposit/withdraw pairs share a unique gas price (analysed {} deposits and {} withdraws with known gas price)",
+                    res.len(),
+                    ds.len(),
+                    ws.len()
+                ),
+                findings: res
+                    .into_iter()
+                    .map(|(d, w)| Finding {
+                        heuristic: self.name(),
+                        pools: [d.pool, w.pool].into_iter().unique().collect(),
+                        addresses: vec![d.from, w.receiver],
+                        score: 1.0 / pairs[&(d.chain, d.gas_price)] as f64,
+                        deposits: vec![d.into()],
+                        withdraws: vec![w.into()],
+                        pattern: None,
+                        details: BTreeMap::from([(
+                            "gas_price",
+                            d.gas_price.unwrap_or_default().to_string(),
+                        )]),
+                    })
+                    .collect(),
+            }],
+        })
+    }
+
+    fn describe(&self, finding: &Finding) -> String {
+        format!(
+            "{} deposited at {} and {} withdrew at {} with the same gas price ({} gwei)",
+            hashstring!(finding.addresses[0]),
+            hashstring!(finding.deposits[0].hash),
+            hashstring!(finding.addresses[1]),
+            hashstring!(finding.withdraws[0].hash),
+            finding
+                .details
+                .get("gas_price")
+                .and_then(|g| g.parse::<u128>().ok())
+                .map(|g| g as f64 / 1e9)
+                .unwrap_or_default()
+        )
+    }
+}
@@ -0,0 +1,239 @@
+//! Heuristics to de-anonymize Tornado Cash users.
+//!
+//! The crate is organized in the same steps as the command line application:
+//!
+//! 1. **Loading** transaction histories obtained from Etherscan ([load_files], or [stream_files]
+//!    for files that do not fit into memory) and event logs obtained via `eth_getLogs`
+//!    ([load_log_files] or [stream_log_files]), see [fetch] for downloading them.
+//! 2. **Decoding** the calls and logs into [Deposit]s and [Withdraw]s ([split_deposit_withdraw],
+//!    [split_deposit_withdraw_logs] and [merge_deposit_withdraw], or [load] for all steps so far).
+//!    Pools are identified by [Pool] handles into the [registry](data::registry).
+//! 3. **Analyzing** them with the [heuristics], either through the [Heuristic] trait or by
+//!    calling the underlying functions in [analysis::analyze] (e.g.,
+//!    [get_address_matches](analysis::analyze::get_address_matches) or
+//!    [match_patterns](analysis::analyze::match_patterns)) directly.
+//!
+//! ```no_run
+//! use std::collections::HashMap;
+//! use tornado_cash_heuristics::{available_heuristics, load, Params};
+//!
+//! let data = load(&[(1, "TORNADO_CASH_0_1ETH.json")], &[], false).unwrap();
+//! let (deposits, withdraws, history) = data.refs();
+//!
+//! for h in available_heuristics().into_iter().filter(|h| h.default_enabled()) {
+//!     let params = Params::new(h.as_ref(), &HashMap::new()).unwrap();
+//!     let report = h.run(&deposits, &withdraws, &history, &params).unwrap();
+//!     println!("{}: {} findings", h.name(), report.findings().count());
+//! }
+//! ```
+
+/// Decoding of calls/logs and the algorithms behind the heuristics
+pub mod analysis;
+/// Clustering of the addresses linked by the findings of the heuristics
+pub mod cluster;
+/// Data model: transactions, logs, deposits, withdraws and pools
+pub mod data;
+/// Errors that occur while loading and decoding
+pub mod error;
+/// Downloading transaction histories (Etherscan) and event logs (JSON-RPC)
+pub mod fetch;
+/// Loading files and grouping deposits/withdraws
+pub mod helpers;
+/// The heuristics and their registry
+pub mod heuristics;
+/// Printing findings as text, JSON (Lines) or CSV and exporting the decoded data
+pub mod output;
+/// Combining the scores of the findings into a linkage risk per address
+pub mod risk;
+
+use analysis::analyze::{get_anonymity_sets, get_relayer_stats};
+use analysis::{AnonymitySet, RelayerStats};
+use helpers::{collect_pools, pair_pools};
+use rayon::prelude::*;
+use std::collections::{BTreeSet, HashSet};
+use web3::types::H256;
+
+pub use analysis::prepare::{
+    merge_deposit_withdraw, split_deposit_withdraw, split_deposit_withdraw_logs,
+};
+pub use analysis::DepositWithdrawPattern;
+pub use data::{Deposit, ESNormalTransaction, Pool, PoolLog, Withdraw};
+pub use error::Error;
+pub use helpers::{load_files, load_log_files, stream_files, stream_log_files};
+pub use heuristics::{
+    available_heuristics, run_heuristics, select_heuristics, Finding, Heuristic, Params, Report,
+    Selected,
+};
+
+/// Deposits and withdraws decoded from transaction histories and event logs
+#[derive(Debug, Default)]
+pub struct Dataset {
+    pub deposits: Vec<Deposit>,
+    pub withdraws: Vec<Withdraw>,
+    /// Further transactions of depositing/withdrawing addresses (see [Dataset::with_history])
+    pub history: Vec<ESNormalTransaction>,
+    /// Transactions and logs that could not be parsed or decoded
+    pub skipped: Vec<Error>,
+}
+
+impl Dataset {
+    /// References to the deposits, withdraws and further transactions, as expected by the
+    /// heuristics
+    pub fn refs(&self) -> (Vec<&Deposit>, Vec<&Withdraw>, Vec<&ESNormalTransaction>) {
+        (
+            self.deposits.iter().collect(),
+            self.withdraws.iter().collect(),
+            self.history.iter().collect(),
+        )
+    }
+
+    /// Load transaction histories of depositing/withdrawing addresses (as obtained from the
+    /// Etherscan `txlist` API) and add them to the dataset. Failed transactions are ignored,
+    /// transactions that cannot be parsed are skipped (or, if `strict` is set, returned as error).
+    ///
+    /// # Arguments
+    ///
+    /// * files - chain IDs and paths of transaction history files (see [load_files])
+    /// * strict - abort at the first transaction that cannot be parsed
+    pub fn with_history(mut self, files: &[(u64, &str)], strict: bool) -> Result<Self, Error> {
+        let history = &mut self.history;
+        let errors = stream_files(files, &|t| t.isError == 0, strict, &mut |_, _, t| {
+            history.push(t);
+            Ok(())
+        })?;
+        self.skipped.extend(errors);
+
+        Ok(self)
+    }
+
+    /// Chains of which no deposit or withdraw belongs to a known pool, e.g., because files of
+    /// another chain were renamed and loaded without their chain ID
+    pub fn unknown_chains(&self) -> Vec<u64> {
+        let chains: BTreeSet<u64> = self
+            .deposits
+            .iter()
+            .map(|d| d.chain)
+            .chain(self.withdraws.iter().map(|w| w.chain))
+            .collect();
+        let known: HashSet<u64> = self
+            .deposits
+            .iter()
+            .filter(|d| d.pool != Pool::UNKNOWN)
+            .map(|d| d.chain)
+            .chain(
+                self.withdraws
+                    .iter()
+                    .filter(|w| w.pool != Pool::UNKNOWN)
+                    .map(|w| w.chain),
+            )
+            .collect();
+
+        chains.into_iter().filter(|c| !known.contains(c)).collect()
+    }
+
+    /// The (effective) anonymity sets of the withdraws of each pool (see
+    /// [get_anonymity_sets](analysis::analyze::get_anonymity_sets)), pools are analyzed in
+    /// parallel. Deposits linked to an address by a finding of `reports` do not count for the
+    /// effective anonymity set.
+    ///
+    /// # Arguments
+    ///
+    /// * reports - reports of the heuristics (see [run_heuristics])
+    pub fn anonymity_sets(&self, reports: &[Report]) -> Vec<(Pool, Vec<AnonymitySet<'_>>)> {
+        let (deposits, withdraws, _) = self.refs();
+        let linked: HashSet<H256> = reports
+            .iter()
+            .flat_map(|report| {
+                report
+                    .findings()
+                    .flat_map(|f| f.deposits.iter().map(|d| d.hash))
+            })
+            .collect();
+
+        pair_pools(&deposits, &withdraws)
+            .into_par_iter()
+            .filter(|(_, (_, w))| !w.is_empty())
+            .map(|(p, (d, w))| (p, get_anonymity_sets(&d, &w, &linked)))
+            .collect()
+    }
+
+    /// Statistics of the relayers of each pool (see
+    /// [get_relayer_stats](analysis::analyze::get_relayer_stats)), pools are analyzed in
+    /// parallel.
+    ///
+    /// # Arguments
+    ///
+    /// * interval - number of blocks of a period for the market shares
+    pub fn relayer_stats(&self, interval: u128) -> Vec<(Pool, Vec<RelayerStats>)> {
+        let (_, withdraws, _) = self.refs();
+
+        collect_pools(&withdraws)
+            .into_par_iter()
+            .map(|(p, w)| (p, get_relayer_stats(&w, interval)))
+            .collect()
+    }
+}
+
+/// Load transaction history files and event log files and decode the deposits and withdraws
+/// contained in them. The transactions and logs are decoded while the files are streamed (see
+/// [stream_files] and [stream_log_files]), so only the deposits and withdraws are kept in
+/// memory. Failed transactions are ignored, transactions and logs that cannot be parsed or
+/// decoded are skipped (see [Dataset::skipped]) or, if `strict` is set, the first one of them
+/// (in the order of the files) is returned as error.
+///
+/// # Arguments
+///
+/// * files - chain IDs and paths of transaction history files (see [load_files])
+/// * log_files - chain IDs and paths of event log files (see [load_log_files])
+/// * strict - abort at the first transaction or log that cannot be parsed or decoded
+pub fn load(
+    files: &[(u64, &str)],
+    log_files: &[(u64, &str)],
+    strict: bool,
+) -> Result<Dataset, Error> {
+    // decode errors are skipped or, in strict mode, abort loading
+    let located = |errors: &mut Vec<Error>, es: Vec<Error>, path: &str, index: usize| {
+        let mut es = es.into_iter().map(|e| e.at(path, index));
+        match es.next() {
+            Some(e) if strict => Err(e),
+            Some(e) => {
+                errors.extend([e].into_iter().chain(es));
+                Ok(())
+            }
+            None => Ok(()),
+        }
+    };
+
+    // divide calls into deposits and withdraws while they are loaded, drop other calls
+    let (mut call_deposits, mut call_withdraws, mut decode_errors) = (vec![], vec![], vec![]);
+    let call_errors = stream_files(files, &|t| t.isError == 0, strict, &mut |path, index, t| {
+        let (ds, ws, es) = split_deposit_withdraw(&[&t]);
+        call_deposits.extend(ds);
+        call_withdraws.extend(ws);
+        located(&mut decode_errors, es, path, index)
+    })?;
+    // the same for logs, drop other events
+    let (mut log_deposits, mut log_withdraws, mut log_decode_errors) = (vec![], vec![], vec![]);
+    let log_errors = stream_log_files(log_files, strict, &mut |path, index, l| {
+        let (ds, ws, es) = split_deposit_withdraw_logs(&[&l]);
+        log_deposits.extend(ds);
+        log_withdraws.extend(ws);
+        located(&mut log_decode_errors, es, path, index)
+    })?;
+    let (deposits, withdraws) = merge_deposit_withdraw(
+        (call_deposits, call_withdraws),
+        (log_deposits, log_withdraws),
+    );
+
+    Ok(Dataset {
+        deposits,
+        withdraws,
+        history: vec![],
+        skipped: call_errors
+            .into_iter()
+            .chain(log_errors)
+            .chain(decode_errors)
+            .chain(log_decode_errors)
+            .collect(),
+    })
+}
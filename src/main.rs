@@ -1,96 +1,681 @@
-mod analysis;
-mod data;
-mod helpers;
-mod subcommands;
-
-use analysis::prepare::split_deposit_withdraw;
-use clap::{arg, command};
-use data::{Deposit, ESNormalTransaction, Withdraw};
-use helpers::load_files;
-use subcommands::{address_matches, multiple_denomination};
-
-type SubcommandFunction =
-    for<'r, 's, 't, 'u> fn(&'r [&'s data::Deposit], &'t [&'u data::Withdraw], bool);
+use clap::{arg, command, ArgMatches, Command};
+use rayon::ThreadPoolBuilder;
+use std::collections::HashMap;
+use std::env;
+use std::error::Error;
+use std::path::Path;
+use std::process::exit;
+use std::time::Duration;
+use tornado_cash_heuristics::cluster::cluster_findings;
+use tornado_cash_heuristics::data::registry::{registry, Registry, MAINNET};
+use tornado_cash_heuristics::error::print_skipped;
+use tornado_cash_heuristics::fetch::{
+    fetch_all, fetch_logs, EtherscanClient, RpcClient, DEFAULT_API_KEY, DEFAULT_BLOCK_RANGE,
+    DEFAULT_END_BLOCK, DEFAULT_PAGE_SIZE, DEFAULT_START_BLOCK, ETHERSCAN_API_URL,
+};
+use tornado_cash_heuristics::helpers::parse_chain_path;
+use tornado_cash_heuristics::output::{
+    is_broken_pipe, print_anonymity_sets, print_relayers, write_tables, Output, OutputFormat,
+};
+use tornado_cash_heuristics::risk::{address_risks, Combiner};
+use tornado_cash_heuristics::{
+    available_heuristics, load, run_heuristics, select_heuristics, Dataset, Deposit,
+    ESNormalTransaction, Finding, Pool, Report, Selected, Withdraw,
+};
 
 fn main() {
-    // "register" available heuristics
-    let available_heuristics = [
-        ("address_match", address_matches as SubcommandFunction),
-        (
-            "multiple_denomination",
-            multiple_denomination as SubcommandFunction,
-        ),
-    ];
-
     // parse command line arguments
     let matches = command!("tornado_cash_heuristics")
         .version("0.1")
         .author("David Herrmann <david.herrmann@protonmail.com>")
         .arg(
-            arg!(--heuristics -e ...)
+            arg!(-e --heuristics ...)
                 .help("Comma-separated list of heuristics to use")
+                .global(true)
                 .takes_value(true)
                 .use_value_delimiter(true)
                 .min_values(1),
         )
-        .arg(arg!(--verbose -v ...).help("Print details (e.g., revealing transactions etc.)"))
-        .arg(arg!(--list -l ...).help("List available heuristics"))
-        .arg(arg!(["files"]).takes_value(true).min_values(1))
+        .arg(
+            arg!(-v --verbose ...)
+                .global(true)
+                .help("Print details (e.g., revealing transactions etc.)"),
+        )
+        .arg(arg!(-l --list ...).help("List available heuristics"))
+        .arg(
+            arg!(-p --param <VALUE>)
+                .required(false)
+                .global(true)
+                .multiple_occurrences(true)
+                .help("Set a parameter of a heuristic (e.g., multiple_denomination.min_pools=3), may be given multiple times")
+                .validator(|s| match s.split_once('=') {
+                    Some((name, _)) if name.contains('.') => Ok(()),
+                    _ => Err("expected HEURISTIC.PARAMETER=VALUE"),
+                }),
+        )
+        .arg(
+            arg!(--clusters)
+                .help("Merge the addresses linked by the findings into clusters and print them after the findings (not with CSV output)"),
+        )
+        .arg(
+            arg!(--risk)
+                .help("Combine the scores of the findings into a linkage risk per address and print it after the findings (not with CSV output)"),
+        )
+        .arg(
+            arg!(--combiner <COMBINER>)
+                .required(false)
+                .help("How the scores of the findings of an address are combined (see --risk)")
+                .possible_values(["max", "noisy-or", "mean"])
+                .default_value("noisy-or"),
+        )
+        .arg(
+            arg!(--weight <VALUE>)
+                .required(false)
+                .multiple_occurrences(true)
+                .help("Multiply the scores of a heuristic by a factor for --risk (e.g., timing=0.5), may be given multiple times")
+                .validator(|s| match s.split_once('=').map(|(_, w)| w.parse::<f64>()) {
+                    Some(Ok(w)) if w >= 0.0 => Ok(()),
+                    _ => Err("expected HEURISTIC=WEIGHT with a non-negative weight"),
+                }),
+        )
+        .arg(
+            arg!(--"sort-by-score")
+                .help("Sort the findings of each group by score (most certain first)"),
+        )
+        .arg(
+            arg!(--"output-format" <FORMAT>)
+                .required(false)
+                .global(true)
+                .help("Format of the results")
+                .possible_values(["text", "json", "jsonl", "csv"])
+                .default_value("text"),
+        )
+        .arg(
+            arg!(--logs <FILE>)
+                .required(false)
+                .global(true)
+                .multiple_occurrences(true)
+                .help("File with Tornado Cash event logs (see fetch-logs), may be given multiple times"),
+        )
+        .arg(
+            arg!(--history <FILE>)
+                .required(false)
+                .global(true)
+                .multiple_occurrences(true)
+                .help("Transaction history of a depositing/withdrawing address (for linked_address), may be given multiple times"),
+        )
+        .arg(
+            arg!(--strict)
+                .global(true)
+                .help("Abort if a transaction or log cannot be parsed or decoded")
+                .conflicts_with("skip-invalid"),
+        )
+        .arg(
+            arg!(--"skip-invalid")
+                .global(true)
+                .help("Skip transactions and logs that cannot be parsed or decoded and report them at the end (default)"),
+        )
+        .arg(
+            arg!(--pools <FILE>)
+                .required(false)
+                .global(true)
+                .help("TOML or JSON file with additional pool/router definitions"),
+        )
+        .arg(
+            arg!(-j --jobs <N>)
+                .required(false)
+                .global(true)
+                .help("Number of threads that run heuristics and analyze pools in parallel (default: number of CPUs), the output does not depend on it")
+                .validator(|s| match s.parse::<usize>() {
+                    Ok(n) if n > 0 => Ok(()),
+                    _ => Err("expected a positive number"),
+                }),
+        )
+        .arg(
+            arg!(--chain <ID>)
+                .required(false)
+                .global(true)
+                .help("ID of the chain files without CHAIN: prefix belong to, unless their name tells the chain (for fetch: the chain to fetch)")
+                .default_value("1")
+                .validator(|s| s.parse::<u64>()),
+        )
+        .arg(
+            arg!(["files"])
+                .takes_value(true)
+                .min_values(1)
+                .help("Transaction history files, prefix a path with CHAIN: (e.g., 56:file.json) to set its chain (default: from names like TORNADO_CASH_56_1BNB.json or --chain)"),
+        )
+        .subcommand(
+            Command::new("fetch")
+                .about("Fetch transaction histories of the Tornado Cash contracts from Etherscan")
+                .arg(
+                    arg!(--"api-key" <KEY>)
+                        .required(false)
+                        .help("Etherscan API key (default: $ETHERSCAN_API_KEY or YourApiKeyToken)"),
+                )
+                .arg(
+                    arg!(--"api-url" <URL>)
+                        .required(false)
+                        .help("Base URL of the Etherscan API")
+                        .default_value(ETHERSCAN_API_URL),
+                )
+                .arg(
+                    arg!(-o --"output-dir" <DIR>)
+                        .required(false)
+                        .help("Directory to store the files in")
+                        .default_value("."),
+                )
+                .arg(
+                    arg!(--"start-block" <BLOCK>)
+                        .required(false)
+                        .help("First block to fetch if there is no stored history yet")
+                        .validator(|s| s.parse::<u128>()),
+                )
+                .arg(
+                    arg!(--"end-block" <BLOCK>)
+                        .required(false)
+                        .help("Last block to fetch")
+                        .validator(|s| s.parse::<u128>()),
+                )
+                .arg(
+                    arg!(--"page-size" <N>)
+                        .required(false)
+                        .help("Number of transactions requested at once")
+                        .validator(|s| s.parse::<usize>()),
+                )
+                .arg(
+                    arg!(--delay <MS>)
+                        .required(false)
+                        .help("Minimum delay between two requests in milliseconds")
+                        .validator(|s| s.parse::<u64>()),
+                ),
+        )
+        .subcommand(
+            Command::new("fetch-logs")
+                .about("Fetch Deposit and Withdrawal events of the Tornado Cash pools via JSON-RPC")
+                .arg(arg!(--"rpc-url" <URL>).help("URL of the JSON-RPC endpoint"))
+                .arg(
+                    arg!(-o --output <FILE>)
+                        .required(false)
+                        .help("File to store the logs in (default: TORNADO_CASH_LOGS.json on mainnet, TORNADO_CASH_<CHAIN>_LOGS.json otherwise)"),
+                )
+                .arg(
+                    arg!(--"start-block" <BLOCK>)
+                        .required(false)
+                        .help("First block to fetch if there are no stored logs yet")
+                        .validator(|s| s.parse::<u128>()),
+                )
+                .arg(
+                    arg!(--"end-block" <BLOCK>)
+                        .required(false)
+                        .help("Last block to fetch (default: latest block)")
+                        .validator(|s| s.parse::<u128>()),
+                )
+                .arg(
+                    arg!(--"block-range" <N>)
+                        .required(false)
+                        .help("Number of blocks requested at once")
+                        .validator(|s| s.parse::<u128>()),
+                ),
+        )
+        .subcommand(
+            Command::new("export")
+                .about("Write the decoded deposits and withdraws to CSV files")
+                .arg(
+                    arg!(--deposits <FILE>)
+                        .required(false)
+                        .help("File to write the deposits to")
+                        .default_value("deposits.csv"),
+                )
+                .arg(
+                    arg!(--withdraws <FILE>)
+                        .required(false)
+                        .help("File to write the withdraws to")
+                        .default_value("withdraws.csv"),
+                )
+                .arg(
+                    arg!(["files"])
+                        .takes_value(true)
+                        .min_values(1)
+                        .help("Transaction history files, prefix a path with CHAIN: (e.g., 56:file.json) to set its chain (default: from names like TORNADO_CASH_56_1BNB.json or --chain)"),
+                ),
+        )
+        .subcommand(
+            Command::new("anonymity")
+                .about("Print the (effective) anonymity set of each withdraw")
+                .arg(
+                    arg!(["files"])
+                        .takes_value(true)
+                        .min_values(1)
+                        .help("Transaction history files, prefix a path with CHAIN: (e.g., 56:file.json) to set its chain (default: from names like TORNADO_CASH_56_1BNB.json or --chain)"),
+                ),
+        )
+        .subcommand(
+            Command::new("relayers")
+                .about("Print withdraw counts, fees and market shares of the relayers of each pool")
+                .arg(
+                    arg!(--interval <BLOCKS>)
+                        .required(false)
+                        .help("Number of blocks of a period for the market shares")
+                        .default_value("200000")
+                        .validator(|s| s.parse::<u128>()),
+                )
+                .arg(
+                    arg!(--"fee-factor" <FACTOR>)
+                        .required(false)
+                        .help("Relayers whose fees deviate from the usual fees by more than this factor are unusual")
+                        .default_value("2")
+                        .validator(|s| s.parse::<f64>()),
+                )
+                .arg(
+                    arg!(--"max-receivers" <N>)
+                        .required(false)
+                        .help("Maximum number of receivers of a relayer with unusual fees (more receivers cannot be told apart)")
+                        .default_value("5")
+                        .validator(|s| s.parse::<usize>()),
+                )
+                .arg(
+                    arg!(["files"])
+                        .takes_value(true)
+                        .min_values(1)
+                        .help("Transaction history files, prefix a path with CHAIN: (e.g., 56:file.json) to set its chain (default: from names like TORNADO_CASH_56_1BNB.json or --chain)"),
+                ),
+        )
         .get_matches();
     let verbose = matches.is_present("verbose");
+    let output_format: OutputFormat = matches
+        .value_of_t("output-format")
+        .unwrap_or(OutputFormat::Text);
+    let clusters = matches.is_present("clusters");
+    if clusters && output_format == OutputFormat::Csv {
+        eprintln!("--clusters cannot be combined with CSV output");
+        exit(1);
+    }
+    let risk = matches.is_present("risk");
+    if risk && output_format == OutputFormat::Csv {
+        eprintln!("--risk cannot be combined with CSV output");
+        exit(1);
+    }
+    // weights of the heuristics for the risk scores
+    let weights: HashMap<String, f64> = matches
+        .values_of("weight")
+        .map(|v| {
+            v.filter_map(|w| w.split_once('='))
+                .filter_map(|(h, w)| Some((h.to_string(), w.parse().ok()?)))
+                .collect()
+        })
+        .unwrap_or_default();
+    if let Some(unknown) = weights
+        .keys()
+        .find(|k| !available_heuristics().iter().any(|h| h.name() == *k))
+    {
+        eprintln!("unknown heuristic '{}' (see --list)", unknown);
+        exit(1);
+    }
+    let chain: u64 = matches.value_of_t("chain").unwrap_or(MAINNET);
+
+    // size of the thread pool used for the analysis (rayon uses all CPUs by default)
+    if let Ok(jobs) = matches.value_of_t::<usize>("jobs") {
+        ThreadPoolBuilder::new()
+            .num_threads(jobs)
+            .build_global()
+            .unwrap();
+    }
+
+    // add user-supplied pools to the built-in ones, this has to happen before anything is loaded
+    if let Some(path) = matches.value_of("pools") {
+        match Registry::from_file(Path::new(path)) {
+            Ok(r) => {
+                Registry::builtin().extend(r).install().unwrap();
+            }
+            Err(e) => {
+                eprintln!("could not load pools from '{}': {}", path, e);
+                exit(1);
+            }
+        }
+    }
+
+    // fetch transaction histories and exit
+    if let Some(fetch_matches) = matches.subcommand_matches("fetch") {
+        let api_key = fetch_matches
+            .value_of("api-key")
+            .map(String::from)
+            .or_else(|| env::var("ETHERSCAN_API_KEY").ok())
+            .unwrap_or_else(|| DEFAULT_API_KEY.to_string());
+        let mut client = EtherscanClient::new(
+            fetch_matches.value_of("api-url").unwrap(),
+            &api_key,
+            chain,
+            fetch_matches
+                .value_of_t("delay")
+                .ok()
+                .map(Duration::from_millis),
+        );
+
+        if let Err(e) = fetch_all(
+            &mut client,
+            Path::new(fetch_matches.value_of("output-dir").unwrap()),
+            fetch_matches
+                .value_of_t("start-block")
+                .unwrap_or(if chain == MAINNET {
+                    DEFAULT_START_BLOCK
+                } else {
+                    0
+                }),
+            fetch_matches
+                .value_of_t("end-block")
+                .unwrap_or(DEFAULT_END_BLOCK),
+            fetch_matches
+                .value_of_t("page-size")
+                .unwrap_or(DEFAULT_PAGE_SIZE),
+        ) {
+            eprintln!("fetching failed: {}", e);
+            exit(1);
+        }
+
+        return;
+    }
+
+    // fetch event logs and exit
+    if let Some(fetch_matches) = matches.subcommand_matches("fetch-logs") {
+        let mut client = match RpcClient::new(fetch_matches.value_of("rpc-url").unwrap()) {
+            Ok(c) => c,
+            Err(e) => {
+                eprintln!("could not connect to JSON-RPC endpoint: {}", e);
+                exit(1);
+            }
+        };
+        let default_output = if client.chain() == MAINNET {
+            "TORNADO_CASH_LOGS.json".to_string()
+        } else {
+            format!("TORNADO_CASH_{}_LOGS.json", client.chain())
+        };
+        let path = Path::new(fetch_matches.value_of("output").unwrap_or(&default_output));
+
+        // only pools on the chain of the endpoint
+        let pools: Vec<_> = Pool::all()
+            .filter(|p| p.chain() == Some(client.chain()))
+            .filter_map(|p| p.address())
+            .collect();
+
+        // DEFAULT_START_BLOCK is the deployment block on mainnet, other chains start at genesis
+        let start_block =
+            fetch_matches
+                .value_of_t("start-block")
+                .unwrap_or(if client.chain() == MAINNET {
+                    DEFAULT_START_BLOCK
+                } else {
+                    0
+                });
+
+        if let Err(e) = fetch_matches
+            .value_of_t("end-block")
+            .or_else(|_| client.block_number())
+            .and_then(|end_block| {
+                fetch_logs(
+                    &mut client,
+                    path,
+                    &pools,
+                    start_block,
+                    end_block,
+                    fetch_matches
+                        .value_of_t("block-range")
+                        .unwrap_or(DEFAULT_BLOCK_RANGE),
+                )
+            })
+            .map(|added| println!("{}: added {} logs", path.display(), added))
+        {
+            eprintln!("fetching failed: {}", e);
+            exit(1);
+        }
+
+        return;
+    }
+
+    // decode transactions and event logs, write them to CSV files and exit
+    if let Some(export_matches) = matches.subcommand_matches("export") {
+        let data = load_dataset(export_matches, chain);
+        let (deposits, withdraws, _) = data.refs();
+
+        if let Err(e) = write_tables(
+            &deposits,
+            &withdraws,
+            Path::new(export_matches.value_of("deposits").unwrap()),
+            Path::new(export_matches.value_of("withdraws").unwrap()),
+        ) {
+            eprintln!("export failed: {}", e);
+            exit(1);
+        }
+        println!(
+            "exported {} deposits, {} withdraws",
+            deposits.len(),
+            withdraws.len()
+        );
+        print_skipped(&data.skipped, verbose);
+
+        return;
+    }
+
+    // compute the anonymity sets of the withdraws, print them and exit
+    if let Some(anonymity_matches) = matches.subcommand_matches("anonymity") {
+        let selected = selected_heuristics(anonymity_matches);
+        let data = load_dataset(anonymity_matches, chain);
+        let (deposits, withdraws, history) = data.refs();
+
+        // deposits that a heuristic linked to an address do not count for the effective anonymity
+        // set
+        let reports = run_selected(&selected, &deposits, &withdraws, &history);
+
+        if let Err(e) = print_anonymity_sets(
+            &data.anonymity_sets(&reports),
+            anonymity_matches
+                .value_of_t("output-format")
+                .unwrap_or(OutputFormat::Text),
+            anonymity_matches.is_present("verbose"),
+        ) {
+            output_failed("printing anonymity sets", e);
+        }
+        print_skipped(&data.skipped, verbose);
+
+        return;
+    }
+
+    // compute statistics of the relayers, print them and exit
+    if let Some(relayers_matches) = matches.subcommand_matches("relayers") {
+        let data = load_dataset(relayers_matches, chain);
+
+        if let Err(e) = print_relayers(
+            &data.relayer_stats(relayers_matches.value_of_t("interval").unwrap()),
+            relayers_matches.value_of_t("fee-factor").unwrap(),
+            relayers_matches.value_of_t("max-receivers").unwrap(),
+            relayers_matches
+                .value_of_t("output-format")
+                .unwrap_or(OutputFormat::Text),
+            relayers_matches.is_present("verbose"),
+        ) {
+            output_failed("printing relayers", e);
+        }
+        print_skipped(&data.skipped, verbose);
+
+        return;
+    }
 
     // if list of heuristics should be printed, print it and exit
     if matches.is_present("list") {
         println!("The following heuristics are present:\n");
-        available_heuristics
-            .iter()
-            .for_each(|(n, _)| println!("- {}", n));
+        available_heuristics().iter().for_each(|h| {
+            println!(
+                "- {}{}: {}",
+                h.name(),
+                if h.default_enabled() { "" } else { " (opt-in)" },
+                h.description()
+            );
+            h.params().iter().for_each(|p| {
+                println!(
+                    "    {}.{} (default: {}): {}",
+                    h.name(),
+                    p.name,
+                    p.default,
+                    p.description
+                )
+            });
+        });
 
         return;
     };
 
-    // load and parse transaction history files specified via command line, filter out errors
-    let files: Vec<&str> = matches
-        .values_of("files")
-        .expect("Please supply path(s) to transaction history file(s)")
-        .collect();
-    let calls: Vec<ESNormalTransaction> =
-        load_files(files, &|t: &ESNormalTransaction| t.isError == 0);
-
-    // divide calls into deposits and withdraws, drop other calls, obtain vectors of references to
-    // Deposit/Withdraw structs for later use
-    let (deposits, withdraws) = split_deposit_withdraw(
-        &calls
-            .iter()
-            .filter(|c| c.to.is_some())
-            .collect::<Vec<&ESNormalTransaction>>(),
-    );
-    let deposit_refs: Vec<&Deposit> = deposits.iter().collect();
-    let withdraw_refs: Vec<&Withdraw> = withdraws.iter().collect();
+    let selected = selected_heuristics(&matches);
+
+    let data = load_dataset(&matches, chain);
+    // obtain vectors of references to Deposit/Withdraw structs for later use
+    let (deposits, withdraws, history) = data.refs();
 
     // run algorithms
-    {
-        println!(
+    let reports = run_selected(&selected, &deposits, &withdraws, &history);
+    let printed = (|| -> Result<(), Box<dyn Error>> {
+        let mut out = Output::new(output_format, verbose)?;
+        out.info(&format!(
             "loaded {} deposits, {} withdraws",
             deposits.len(),
             withdraws.len(),
-        );
+        ))?;
+        // findings of all heuristics, kept for clustering and risk scores
+        let mut findings: Vec<Finding> = vec![];
+        for ((h, _), mut report) in selected.iter().zip(reports) {
+            if matches.is_present("sort-by-score") {
+                report.sort_by_score();
+            }
+            if clusters || risk {
+                findings.extend(report.findings().cloned());
+            }
+            out.report(h.as_ref(), report)?;
+        }
+        if clusters {
+            out.clusters(cluster_findings(
+                &findings.iter().collect::<Vec<&Finding>>(),
+                &deposits,
+                &withdraws,
+            ))?;
+        }
+        if risk {
+            out.risks(address_risks(
+                &findings.iter().collect::<Vec<&Finding>>(),
+                matches.value_of_t("combiner").unwrap_or(Combiner::NoisyOr),
+                &weights,
+            ))?;
+        }
+        out.finish(deposits.len(), withdraws.len(), data.skipped.len())
+    })();
+    if let Err(e) = printed {
+        output_failed("printing findings", e);
+    }
 
-        // if heuristics were selected, apply them
-        if let Some(heuristics) = matches.values_of("heuristics") {
-            let heuristics_vec = heuristics.collect::<Vec<&str>>();
+    print_skipped(&data.skipped, verbose);
+}
 
-            available_heuristics.iter().for_each(|(name, f)| {
-                if heuristics_vec.contains(name) {
-                    f(&deposit_refs, &withdraw_refs, verbose)
-                } else {
-                }
+/// Select the heuristics given with -e (or all that are not opt-in) and set their parameters to
+/// the values given with -p, exits if a heuristic or a parameter is unknown.
+///
+/// # Arguments
+///
+/// * matches - the parsed arguments of the (sub)command
+fn selected_heuristics(matches: &ArgMatches) -> Vec<Selected> {
+    let names: Option<Vec<&str>> = matches.values_of("heuristics").map(|v| v.collect());
+    let param_values: HashMap<String, String> = matches
+        .values_of("param")
+        .map(|v| {
+            v.filter_map(|p| p.split_once('='))
+                .map(|(n, v)| (n.to_string(), v.to_string()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    // parameters are set before loading any data, so unknown ones are reported right away
+    select_heuristics(names.as_deref(), &param_values).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    })
+}
+
+/// Exit after printing the results failed, silently if stdout was closed (e.g., by `head`).
+///
+/// # Arguments
+///
+/// * what - what was printed
+/// * e - the error returned by the printing function
+fn output_failed(what: &str, e: Box<dyn Error>) -> ! {
+    if is_broken_pipe(e.as_ref()) {
+        exit(0);
+    }
+
+    eprintln!("{} failed: {}", what, e);
+    exit(1);
+}
+
+/// Run the selected heuristics (see [run_heuristics]), exits if a heuristic fails.
+///
+/// # Arguments
+///
+/// * selected - the heuristics to run together with their parameters
+/// * deposits - the deposits to analyze
+/// * withdraws - the withdraws to analyze
+/// * history - further transactions of depositing/withdrawing addresses
+fn run_selected(
+    selected: &[Selected],
+    deposits: &[&Deposit],
+    withdraws: &[&Withdraw],
+    history: &[&ESNormalTransaction],
+) -> Vec<Report> {
+    run_heuristics(selected, deposits, withdraws, history).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        exit(1);
+    })
+}
+
+/// Load the transaction history files and event log files given on the command line and decode
+/// the deposits and withdraws, exits if a file cannot be read (or, with --strict, if a
+/// transaction or log is invalid).
+///
+/// # Arguments
+///
+/// * matches - the parsed arguments of the (sub)command that takes the files
+/// * chain - chain ID of files without CHAIN: prefix
+fn load_dataset(matches: &ArgMatches, chain: u64) -> Dataset {
+    let files: Vec<(u64, &str)> = matches
+        .values_of("files")
+        .map(|v| v.map(|f| parse_chain_path(f, chain)).collect())
+        .unwrap_or_default();
+    let log_files: Vec<(u64, &str)> = matches
+        .values_of("logs")
+        .map(|v| v.map(|f| parse_chain_path(f, chain)).collect())
+        .unwrap_or_default();
+    let history_files: Vec<(u64, &str)> = matches
+        .values_of("history")
+        .map(|v| v.map(|f| parse_chain_path(f, chain)).collect())
+        .unwrap_or_default();
+    if files.is_empty() && log_files.is_empty() {
+        eprintln!("Please supply path(s) to transaction history file(s)");
+        exit(1);
+    }
+
+    // rows that could not be parsed or decoded are either fatal or skipped
+    let strict = matches.is_present("strict");
+    match load(&files, &log_files, strict)
+        .and_then(|data| data.with_history(&history_files, strict))
+    {
+        Ok(data) => {
+            data.unknown_chains().into_iter().for_each(|c| {
+                eprintln!(
+                    "warning: no deposit or withdraw on {} belongs to a known pool, pass the chain of the files with CHAIN: or --chain",
+                    registry().chain_name(c)
+                )
             });
-        // otherwise apply all
-        } else {
-            available_heuristics
-                .iter()
-                .for_each(|(_, f)| f(&deposit_refs, &withdraw_refs, verbose))
+
+            data
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            exit(1);
         }
     }
 }
@@ -0,0 +1,655 @@
+use crate::analysis::{AnonymitySet, RelayerStats};
+use crate::cluster::Cluster;
+use crate::data::{Deposit, Pool, Withdraw};
+use crate::hashstring;
+use crate::heuristics::{Finding, Heuristic, Report};
+use crate::risk::AddressRisk;
+use csv::Writer;
+use itertools::Itertools;
+use serde::Serialize;
+use std::error::Error;
+use std::io::{stdout, ErrorKind, Stdout, Write};
+use std::path::Path;
+use std::str::FromStr;
+use web3::types::{H160, H256};
+
+/// Format in which the results of the heuristics are printed
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OutputFormat {
+    /// Human-readable text (the default)
+    Text,
+    /// A single JSON object containing all findings and a summary of the run
+    Json,
+    /// One JSON object per line, one for each finding and a summary of the run as last line
+    JsonLines,
+    /// One line per finding, lists (e.g., of addresses) are separated by semicolons
+    Csv,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" => Ok(OutputFormat::JsonLines),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("unknown output format '{}'", s)),
+        }
+    }
+}
+
+const CSV_HEADER: [&str; 10] = [
+    "heuristic",
+    "pools",
+    "addresses",
+    "score",
+    "deposit_hashes",
+    "deposit_blocks",
+    "withdraw_hashes",
+    "withdraw_blocks",
+    "pattern",
+    "details",
+];
+
+impl Finding {
+    // fields of a line of CSV output (see CSV_HEADER)
+    fn csv_record(&self) -> [String; 10] {
+        fn join<T>(ts: &[T], f: impl Fn(&T) -> String) -> String {
+            ts.iter().map(f).collect::<Vec<String>>().join(";")
+        }
+
+        [
+            self.heuristic.to_string(),
+            join(&self.pools, |p| p.to_string()),
+            join(&self.addresses, |a| hashstring!(a)),
+            self.score.to_string(),
+            join(&self.deposits, |d| hashstring!(d.hash)),
+            join(&self.deposits, |d| d.block.to_string()),
+            join(&self.withdraws, |w| hashstring!(w.hash)),
+            join(&self.withdraws, |w| w.block.to_string()),
+            self.pattern
+                .as_ref()
+                .map(|p| p.to_string())
+                .unwrap_or_default(),
+            self.details
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, v))
+                .collect::<Vec<String>>()
+                .join(";"),
+        ]
+    }
+}
+
+/// Number of findings of a heuristic
+#[derive(Serialize, Debug, Clone)]
+pub struct HeuristicSummary {
+    pub name: &'static str,
+    pub findings: usize,
+}
+
+/// Summary of a run, printed after all heuristics were applied
+#[derive(Serialize, Debug, Clone)]
+pub struct Summary {
+    pub deposits: usize,
+    pub withdraws: usize,
+    /// Number of transactions/logs that could not be parsed or decoded
+    pub skipped: usize,
+    pub heuristics: Vec<HeuristicSummary>,
+}
+
+// a line of JSON Lines output
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Record<'a> {
+    Finding(&'a Finding),
+    Cluster(&'a Cluster),
+    Risk(&'a AddressRisk),
+    Summary(&'a Summary),
+}
+
+// the object printed in JSON mode
+#[derive(Serialize)]
+struct Document<'a> {
+    findings: &'a [Finding],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    clusters: Option<&'a [Cluster]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    risks: Option<&'a [AddressRisk]>,
+    summary: &'a Summary,
+}
+
+/// Receives the results of the heuristics and prints them in the selected format: free text is
+/// only printed in text mode, findings only in JSON (Lines) and CSV mode.
+pub struct Output {
+    format: OutputFormat,
+    verbose: bool,
+    findings: Vec<Finding>,
+    clusters: Option<Vec<Cluster>>,
+    risks: Option<Vec<AddressRisk>>,
+    heuristics: Vec<HeuristicSummary>,
+    csv: Option<Writer<Stdout>>,
+}
+
+impl Output {
+    pub fn new(format: OutputFormat, verbose: bool) -> Result<Self, Box<dyn Error>> {
+        // the header is written right away, so it is present even if nothing is found
+        let csv = match format {
+            OutputFormat::Csv => {
+                let mut w = Writer::from_writer(stdout());
+                w.write_record(CSV_HEADER)?;
+                Some(w)
+            }
+            _ => None,
+        };
+
+        Ok(Output {
+            format,
+            verbose,
+            findings: vec![],
+            clusters: None,
+            risks: None,
+            heuristics: vec![],
+            csv,
+        })
+    }
+
+    /// Print a line of text (in text mode)
+    pub fn info(&self, text: &str) -> Result<(), Box<dyn Error>> {
+        if self.format == OutputFormat::Text {
+            writeln!(stdout(), "{}", text)?;
+        }
+
+        Ok(())
+    }
+
+    /// Print the report of a heuristic: the title of each group (and, if details were requested,
+    /// a description of each finding) in text mode, the findings otherwise.
+    ///
+    /// # Arguments
+    ///
+    /// * heuristic - the heuristic that produced the report
+    /// * report - the result of running the heuristic
+    pub fn report(
+        &mut self,
+        heuristic: &dyn Heuristic,
+        report: Report,
+    ) -> Result<(), Box<dyn Error>> {
+        self.heuristics.push(HeuristicSummary {
+            name: heuristic.name(),
+            findings: report.findings().count(),
+        });
+
+        for group in report.groups {
+            if self.format == OutputFormat::Text {
+                writeln!(stdout(), "{}", group.title)?;
+            }
+
+            for finding in group.findings {
+                match self.format {
+                    OutputFormat::Text => {
+                        if self.verbose {
+                            writeln!(
+                                stdout(),
+                                "{} [score {:.3}]",
+                                heuristic.describe(&finding),
+                                finding.score
+                            )?
+                        }
+                    }
+                    OutputFormat::Csv => {
+                        if let Some(w) = self.csv.as_mut() {
+                            w.write_record(finding.csv_record())?;
+                        }
+                    }
+                    // print findings right away, so they can be processed while the run continues
+                    OutputFormat::JsonLines => writeln!(
+                        stdout(),
+                        "{}",
+                        serde_json::to_string(&Record::Finding(&finding))?
+                    )?,
+                    OutputFormat::Json => self.findings.push(finding),
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Print clusters of linked addresses: the number of clusters (and, if details were requested,
+    /// a description of each cluster) in text mode, the clusters in JSON (Lines) mode. Clusters
+    /// are not printed in CSV mode.
+    ///
+    /// # Arguments
+    ///
+    /// * clusters - the clusters computed from the findings of all heuristics
+    pub fn clusters(&mut self, clusters: Vec<Cluster>) -> Result<(), Box<dyn Error>> {
+        match self.format {
+            OutputFormat::Text => {
+                writeln!(
+                    stdout(),
+                    "{} clusters of linked addresses ({} with more than one address)",
+                    clusters.len(),
+                    clusters.iter().filter(|c| c.addresses.len() > 1).count()
+                )?;
+                if self.verbose {
+                    clusters
+                        .iter()
+                        .try_for_each(|c| writeln!(stdout(), "{}", c.describe()))?;
+                }
+            }
+            OutputFormat::Csv => (),
+            OutputFormat::JsonLines => clusters.iter().try_for_each(|c| {
+                writeln!(stdout(), "{}", serde_json::to_string(&Record::Cluster(c))?)?;
+                Ok::<_, Box<dyn Error>>(())
+            })?,
+            OutputFormat::Json => self.clusters = Some(clusters),
+        }
+
+        Ok(())
+    }
+
+    /// Print the linkage risk of addresses: the number of addresses (and, if details were
+    /// requested, a line per address) in text mode, the risks in JSON (Lines) mode. Risks are not
+    /// printed in CSV mode.
+    ///
+    /// # Arguments
+    ///
+    /// * risks - the risks of the addresses contained in the findings, sorted by risk
+    pub fn risks(&mut self, risks: Vec<AddressRisk>) -> Result<(), Box<dyn Error>> {
+        match self.format {
+            OutputFormat::Text => {
+                writeln!(
+                    stdout(),
+                    "{} addresses with a linkage risk ({} of at least 0.9)",
+                    risks.len(),
+                    risks.iter().filter(|r| r.risk >= 0.9).count()
+                )?;
+                if self.verbose {
+                    risks
+                        .iter()
+                        .try_for_each(|r| writeln!(stdout(), "{}", r.describe()))?;
+                }
+            }
+            OutputFormat::Csv => (),
+            OutputFormat::JsonLines => risks.iter().try_for_each(|r| {
+                writeln!(stdout(), "{}", serde_json::to_string(&Record::Risk(r))?)?;
+                Ok::<_, Box<dyn Error>>(())
+            })?,
+            OutputFormat::Json => self.risks = Some(risks),
+        }
+
+        Ok(())
+    }
+
+    /// Print the summary of the run (and, in JSON mode, all findings). There is no summary in CSV
+    /// mode.
+    ///
+    /// # Arguments
+    ///
+    /// * deposits - number of loaded deposits
+    /// * withdraws - number of loaded withdraws
+    /// * skipped - number of transactions/logs that could not be parsed or decoded
+    pub fn finish(
+        self,
+        deposits: usize,
+        withdraws: usize,
+        skipped: usize,
+    ) -> Result<(), Box<dyn Error>> {
+        let summary = Summary {
+            deposits,
+            withdraws,
+            skipped,
+            heuristics: self.heuristics,
+        };
+
+        match self.format {
+            OutputFormat::Text => (),
+            OutputFormat::Csv => {
+                if let Some(mut w) = self.csv {
+                    w.flush()?;
+                }
+            }
+            OutputFormat::JsonLines => writeln!(
+                stdout(),
+                "{}",
+                serde_json::to_string(&Record::Summary(&summary))?
+            )?,
+            OutputFormat::Json => writeln!(
+                stdout(),
+                "{}",
+                serde_json::to_string_pretty(&Document {
+                    findings: &self.findings,
+                    clusters: self.clusters.as_deref(),
+                    risks: self.risks.as_deref(),
+                    summary: &summary,
+                })?
+            )?,
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether printing failed because stdout was closed (e.g., the output was piped into `head`),
+/// which is not an error
+///
+/// # Arguments
+///
+/// * e - the error returned by one of the printing functions
+pub fn is_broken_pipe(e: &(dyn Error + 'static)) -> bool {
+    let io = match e.downcast_ref::<csv::Error>() {
+        Some(c) => match c.kind() {
+            csv::ErrorKind::Io(io) => Some(io),
+            _ => None,
+        },
+        None => e.downcast_ref::<std::io::Error>(),
+    };
+
+    io.map(|io| io.kind() == ErrorKind::BrokenPipe)
+        .unwrap_or_default()
+}
+
+// a line of the deposit table written by write_tables
+#[derive(Serialize)]
+struct DepositRow {
+    chain: u64,
+    hash: H256,
+    block: u128,
+    timestamp: Option<u128>,
+    pool: Pool,
+    from: H160,
+}
+
+// a line of the withdraw table written by write_tables
+#[derive(Serialize)]
+struct WithdrawRow {
+    chain: u64,
+    hash: H256,
+    block: u128,
+    timestamp: Option<u128>,
+    pool: Pool,
+    receiver: H160,
+    relayer: H160,
+    // decimal string, since the fee does not necessarily fit into 64 bits
+    fee: String,
+}
+
+/// Write the decoded deposits and withdraws to two CSV files (one line per deposit/withdraw).
+///
+/// # Arguments
+///
+/// * deposits - the deposits to write
+/// * withdraws - the withdraws to write
+/// * deposits_path - path of the file the deposits are written to
+/// * withdraws_path - path of the file the withdraws are written to
+pub fn write_tables(
+    deposits: &[&Deposit],
+    withdraws: &[&Withdraw],
+    deposits_path: &Path,
+    withdraws_path: &Path,
+) -> Result<(), Box<dyn Error>> {
+    let mut w = Writer::from_path(deposits_path)?;
+    deposits.iter().try_for_each(|d| {
+        w.serialize(DepositRow {
+            chain: d.chain,
+            hash: d.transaction_hash,
+            block: d.block_number,
+            timestamp: d.timestamp,
+            pool: d.pool,
+            from: d.from,
+        })
+    })?;
+    w.flush()?;
+
+    let mut w = Writer::from_path(withdraws_path)?;
+    withdraws.iter().try_for_each(|wi| {
+        w.serialize(WithdrawRow {
+            chain: wi.chain,
+            hash: wi.transaction_hash,
+            block: wi.block_number,
+            timestamp: wi.timestamp,
+            pool: wi.pool,
+            receiver: wi.receiver,
+            relayer: wi.relayer,
+            fee: wi.fee.to_string(),
+        })
+    })?;
+    w.flush()?;
+
+    Ok(())
+}
+
+// a point of the anonymity set time series printed by print_anonymity_sets
+#[derive(Serialize)]
+struct AnonymitySetRow {
+    chain: u64,
+    pool: Pool,
+    block: u128,
+    // unknown for withdraws that were only found in the logs
+    timestamp: Option<u128>,
+    hash: H256,
+    anonymity_set: usize,
+    effective_anonymity_set: usize,
+}
+
+/// Print the anonymity sets of the withdraws of each pool: in text mode, a line summarizing the
+/// anonymity sets of each pool (and, if details were requested, a line per withdraw), otherwise
+/// one record per withdraw (sorted by pool and block, i.e., a time series for each pool).
+///
+/// # Arguments
+///
+/// * pools - the anonymity sets of the withdraws of each pool, sorted by block
+/// * format - the format to print the anonymity sets in
+/// * verbose - whether to print each withdraw in text mode
+pub fn print_anonymity_sets(
+    pools: &[(Pool, Vec<AnonymitySet>)],
+    format: OutputFormat,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    let rows = || {
+        pools.iter().flat_map(|(p, sets)| {
+            sets.iter().map(|s| AnonymitySetRow {
+                chain: s.withdraw.chain,
+                pool: *p,
+                block: s.withdraw.block_number,
+                timestamp: s.withdraw.timestamp,
+                hash: s.withdraw.transaction_hash,
+                anonymity_set: s.size,
+                effective_anonymity_set: s.effective,
+            })
+        })
+    };
+
+    match format {
+        OutputFormat::Text => pools.iter().try_for_each(|(p, sets)| {
+            // minimum, median and maximum
+            let stats = |f: &dyn Fn(&AnonymitySet) -> usize| {
+                let values: Vec<usize> = sets.iter().map(f).sorted().collect();
+                format!(
+                    "{}/{}/{}",
+                    values.first().unwrap_or(&0),
+                    values.get(values.len() / 2).unwrap_or(&0),
+                    values.last().unwrap_or(&0)
+                )
+            };
+            writeln!(
+                stdout(),
+                "{} withdraws from the {} pool, anonymity set min/median/max: {}, effective: {}",
+                sets.len(),
+                p,
+                stats(&|s| s.size),
+                stats(&|s| s.effective)
+            )?;
+            if verbose {
+                sets.iter().try_for_each(|s| {
+                    writeln!(
+                        stdout(),
+                        "{} (block {}): {} deposits before, {} of them not linked",
+                        hashstring!(s.withdraw.transaction_hash),
+                        s.withdraw.block_number,
+                        s.size,
+                        s.effective
+                    )
+                })?;
+            }
+
+            Ok::<_, std::io::Error>(())
+        })?,
+        OutputFormat::Csv => {
+            let mut w = Writer::from_writer(stdout());
+            rows().try_for_each(|r| w.serialize(r))?;
+            w.flush()?;
+        }
+        OutputFormat::JsonLines => rows().try_for_each(|r| {
+            writeln!(stdout(), "{}", serde_json::to_string(&r)?)?;
+            Ok::<_, Box<dyn Error>>(())
+        })?,
+        OutputFormat::Json => writeln!(
+            stdout(),
+            "{}",
+            serde_json::to_string_pretty(&rows().collect::<Vec<AnonymitySetRow>>())?
+        )?,
+    }
+
+    Ok(())
+}
+
+// a line of the relayer table printed by print_relayers in CSV mode
+#[derive(Serialize)]
+struct RelayerRow {
+    chain: u64,
+    pool: Pool,
+    relayer: H160,
+    withdraws: usize,
+    receivers: usize,
+    first_block: u128,
+    last_block: u128,
+    fee_min: u128,
+    fee_median: u128,
+    fee_max: u128,
+    relative_fee: f64,
+    unusual_fee: bool,
+    // first block of each period and the share in it, separated by semicolons
+    market_share: String,
+}
+
+// a relayer printed by print_relayers in JSON (Lines) mode
+#[derive(Serialize)]
+struct RelayerRecord<'a> {
+    chain: u64,
+    #[serde(flatten)]
+    stats: &'a RelayerStats,
+    unusual_fee: bool,
+}
+
+/// Print the relayers of each pool: in text mode, a line summarizing the relayers of each pool
+/// (and, if details were requested, a line per relayer), otherwise one record per relayer and
+/// pool. Relayers whose fees [fingerprint](RelayerStats::fingerprints) their users are marked.
+///
+/// # Arguments
+///
+/// * pools - the relayers of each pool, sorted by the number of withdraws
+/// * fee_factor - relayers whose fees deviate from the usual fees by more than this factor are
+///   unusual
+/// * max_receivers - relayers with more receivers than this are never unusual
+/// * format - the format to print the relayers in
+/// * verbose - whether to print each relayer in text mode
+pub fn print_relayers(
+    pools: &[(Pool, Vec<RelayerStats>)],
+    fee_factor: f64,
+    max_receivers: usize,
+    format: OutputFormat,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    let records = || {
+        pools.iter().flat_map(|(p, relayers)| {
+            relayers.iter().map(|r| RelayerRecord {
+                chain: p.chain().unwrap_or_default(),
+                stats: r,
+                unusual_fee: r.fingerprints(fee_factor, max_receivers),
+            })
+        })
+    };
+
+    match format {
+        OutputFormat::Text => pools.iter().try_for_each(|(p, relayers)| {
+            writeln!(
+                stdout(),
+                "{} withdraws from the {} pool by {} relayers ({} with unusual fees)",
+                relayers.iter().map(|r| r.withdraws).sum::<usize>(),
+                p,
+                relayers.len(),
+                relayers
+                    .iter()
+                    .filter(|r| r.fingerprints(fee_factor, max_receivers))
+                    .count()
+            )?;
+            if verbose {
+                relayers.iter().try_for_each(|r| {
+                    writeln!(
+                        stdout(),
+                        "{}: {} withdraws to {} receivers in blocks {} to {}, fees min/median/max: {}/{}/{} ({:.2}x the usual fee){}",
+                        hashstring!(r.relayer),
+                        r.withdraws,
+                        r.receivers.len(),
+                        r.first_block,
+                        r.last_block,
+                        r.fees.min,
+                        r.fees.median,
+                        r.fees.max,
+                        r.relative_fee,
+                        if r.fingerprints(fee_factor, max_receivers) {
+                            format!(
+                                ", unusual fees identify {}",
+                                r.receivers.iter().map(|a| hashstring!(a)).join(", ")
+                            )
+                        } else {
+                            String::new()
+                        }
+                    )
+                })?;
+            }
+
+            Ok::<_, std::io::Error>(())
+        })?,
+        OutputFormat::Csv => {
+            let mut w = Writer::from_writer(stdout());
+            records().try_for_each(|r| {
+                w.serialize(RelayerRow {
+                    chain: r.chain,
+                    pool: r.stats.pool,
+                    relayer: r.stats.relayer,
+                    withdraws: r.stats.withdraws,
+                    receivers: r.stats.receivers.len(),
+                    first_block: r.stats.first_block,
+                    last_block: r.stats.last_block,
+                    fee_min: r.stats.fees.min,
+                    fee_median: r.stats.fees.median,
+                    fee_max: r.stats.fees.max,
+                    relative_fee: r.stats.relative_fee,
+                    unusual_fee: r.unusual_fee,
+                    market_share: r
+                        .stats
+                        .market_share
+                        .iter()
+                        .map(|(b, s)| format!("{}={}", b, s))
+                        .join(";"),
+                })
+            })?;
+            w.flush()?;
+        }
+        OutputFormat::JsonLines => records().try_for_each(|r| {
+            writeln!(stdout(), "{}", serde_json::to_string(&r)?)?;
+            Ok::<_, Box<dyn Error>>(())
+        })?,
+        OutputFormat::Json => writeln!(
+            stdout(),
+            "{}",
+            serde_json::to_string_pretty(&records().collect::<Vec<RelayerRecord>>())?
+        )?,
+    }
+
+    Ok(())
+}
@@ -0,0 +1,119 @@
+use crate::hashstring;
+use crate::heuristics::Finding;
+use itertools::Itertools;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use web3::types::H160;
+
+/// How the scores of the findings of an address are combined into its linkage risk
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Combiner {
+    /// The highest score (the most certain finding)
+    Max,
+    /// 1 - (1 - s1) * (1 - s2) * ..., i.e., the findings are treated as independent evidence
+    NoisyOr,
+    /// The average score
+    Mean,
+}
+
+impl FromStr for Combiner {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        match s {
+            "max" => Ok(Combiner::Max),
+            "noisy-or" => Ok(Combiner::NoisyOr),
+            "mean" => Ok(Combiner::Mean),
+            _ => Err(format!("unknown combiner '{}'", s)),
+        }
+    }
+}
+
+impl Combiner {
+    // combine the (weighted) scores of the findings of an address
+    fn combine(&self, scores: &[f64]) -> f64 {
+        match self {
+            Combiner::Max => scores.iter().copied().fold(0.0, f64::max),
+            Combiner::NoisyOr => 1.0 - scores.iter().map(|s| 1.0 - s).product::<f64>(),
+            Combiner::Mean => scores.iter().sum::<f64>() / scores.len().max(1) as f64,
+        }
+    }
+}
+
+/// Linkage risk of an address: how certain it is that the address is linked to another address
+/// (or that its deposits are linked to withdraws)
+#[derive(Serialize, Debug, Clone)]
+pub struct AddressRisk {
+    pub address: H160,
+    /// Combined score of the findings containing the address, between 0 and 1
+    pub risk: f64,
+    /// Number of findings containing the address
+    pub findings: usize,
+    /// Highest score of the address's findings by heuristic
+    pub heuristics: BTreeMap<&'static str, f64>,
+}
+
+impl AddressRisk {
+    /// One-line description of the risk, printed with `--verbose`
+    pub fn describe(&self) -> String {
+        format!(
+            "{}: {:.3} ({} findings: {})",
+            hashstring!(self.address),
+            self.risk,
+            self.findings,
+            self.heuristics
+                .iter()
+                .map(|(h, s)| format!("{} {:.3}", h, s))
+                .join(", ")
+        )
+    }
+}
+
+/// Combine the scores of the findings into a linkage risk for each address contained in any
+/// finding. The score of a finding is multiplied by the weight of its heuristic (1 if no weight
+/// is given, the result is capped at 1) before the scores are combined. The risks are sorted from
+/// highest to lowest.
+///
+/// # Arguments
+///
+/// * findings - the findings of any heuristics
+/// * combiner - how the scores of the findings of an address are combined
+/// * weights - weights of the heuristics by name
+pub fn address_risks(
+    findings: &[&Finding],
+    combiner: Combiner,
+    weights: &HashMap<String, f64>,
+) -> Vec<AddressRisk> {
+    findings
+        .iter()
+        .flat_map(|f| {
+            let score = (f.score * weights.get(f.heuristic).copied().unwrap_or(1.0)).min(1.0);
+            f.addresses
+                .iter()
+                .unique()
+                .map(move |a| (*a, (f.heuristic, score)))
+        })
+        .into_group_map()
+        .into_iter()
+        .map(|(address, scores)| {
+            let values: Vec<f64> = scores.iter().map(|(_, s)| *s).collect();
+
+            AddressRisk {
+                address,
+                risk: combiner.combine(&values),
+                findings: scores.len(),
+                heuristics: scores.into_iter().fold(BTreeMap::new(), |mut m, (h, s)| {
+                    let e = m.entry(h).or_insert(s);
+                    *e = s.max(*e);
+                    m
+                }),
+            }
+        })
+        .sorted_by(|a, b| {
+            b.risk
+                .total_cmp(&a.risk)
+                .then_with(|| a.address.cmp(&b.address))
+        })
+        .collect()
+}
@@ -0,0 +1,115 @@
+// every test file uses only some of the helpers
+#![allow(dead_code)]
+
+use std::fs::{create_dir_all, remove_dir_all};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+/// A request received by a [MockServer]
+#[derive(Debug, Clone)]
+pub struct Request {
+    /// Path including the query string
+    pub path: String,
+    pub body: String,
+}
+
+/// Minimal HTTP server on a random local port that answers every request with the JSON returned
+/// by a handler and records the requests
+pub struct MockServer {
+    pub url: String,
+    pub requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl MockServer {
+    /// Start serving in a background thread, the thread ends with the test process.
+    ///
+    /// # Arguments
+    ///
+    /// * handler - called with the request and the number of earlier requests, returns the
+    ///   response body
+    pub fn start(handler: impl Fn(&Request, usize) -> String + Send + 'static) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(vec![]));
+        let recorded = requests.clone();
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let mut reader = BufReader::new(&stream);
+
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                let path = line.split(' ').nth(1).unwrap_or_default().to_string();
+                // only the length of the body is of interest in the headers
+                let mut length = 0;
+                loop {
+                    let mut header = String::new();
+                    reader.read_line(&mut header).unwrap();
+                    if header.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = header.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+
+                let request = Request {
+                    path,
+                    body: String::from_utf8(body).unwrap(),
+                };
+                let response = {
+                    let mut requests = recorded.lock().unwrap();
+                    let response = handler(&request, requests.len());
+                    requests.push(request);
+                    response
+                };
+
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    response.len(),
+                    response
+                )
+                .unwrap();
+            }
+        });
+
+        MockServer { url, requests }
+    }
+
+    /// The requests received so far
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// An empty directory for the files of a test, removed when dropped
+pub struct TempDir(pub PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!(
+            "tornado_cash_heuristics_{}_{}",
+            name,
+            std::process::id()
+        ));
+        let _ = remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+
+        TempDir(dir)
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = remove_dir_all(&self.0);
+    }
+}
@@ -0,0 +1,208 @@
+mod common;
+
+use common::{MockServer, TempDir};
+use serde_json::{from_str, json, to_string, Value};
+use std::fs::{read_to_string, write};
+use std::path::Path;
+use std::time::Duration;
+use tornado_cash_heuristics::data::ESNormalTransactionStrings;
+use tornado_cash_heuristics::fetch::{fetch_account, EtherscanClient};
+
+const ADDRESS: &str = "0x12d66f87a04a9e220743712ce6d9bb1b5616b8fc";
+
+// a transaction in the format of the Etherscan `txlist` API
+fn transaction(block: u128, n: u64) -> Value {
+    json!({
+        "blockNumber": block.to_string(),
+        "timeStamp": (1600000000 + block * 13).to_string(),
+        "hash": format!("0x{:064x}", n),
+        "nonce": "0",
+        "blockHash": format!("0x{:064x}", block),
+        "transactionIndex": "0",
+        "from": "0x1111111111111111111111111111111111111111",
+        "to": ADDRESS,
+        "value": "100000000000000000",
+        "gas": "1000000",
+        "gasPrice": "20000000000",
+        "isError": "0",
+        "txreceipt_status": "1",
+        "input": "0xb214faa5",
+        "contractAddress": "",
+        "cumulativeGasUsed": "0",
+        "gasUsed": "0",
+        "confirmations": "0"
+    })
+}
+
+// numeric value of a parameter in the query string of a request
+fn query_param(path: &str, name: &str) -> u128 {
+    path.split_once('?')
+        .unwrap()
+        .1
+        .split('&')
+        .filter_map(|p| p.split_once('='))
+        .find(|(n, _)| *n == name)
+        .map(|(_, v)| v.parse().unwrap())
+        .unwrap()
+}
+
+// answers like Etherscan: at most `offset` transactions from `startblock` on, sorted by block
+fn etherscan(transactions: Vec<Value>) -> MockServer {
+    MockServer::start(move |request, _| {
+        let start = query_param(&request.path, "startblock");
+        let end = query_param(&request.path, "endblock");
+        let offset = query_param(&request.path, "offset") as usize;
+        let page: Vec<&Value> = transactions
+            .iter()
+            .filter(|t| {
+                let block: u128 = t["blockNumber"].as_str().unwrap().parse().unwrap();
+                start <= block && block <= end
+            })
+            .take(offset)
+            .collect();
+
+        if page.is_empty() {
+            json!({"status": "0", "message": "No transactions found", "result": []}).to_string()
+        } else {
+            json!({"status": "1", "message": "OK", "result": page}).to_string()
+        }
+    })
+}
+
+fn client(server: &MockServer) -> EtherscanClient {
+    EtherscanClient::new(
+        &format!("{}/api", server.url),
+        "key",
+        1,
+        Some(Duration::from_millis(1)),
+    )
+}
+
+fn start_blocks(server: &MockServer) -> Vec<u128> {
+    server
+        .requests()
+        .iter()
+        .map(|r| query_param(&r.path, "startblock"))
+        .collect()
+}
+
+fn stored(path: &Path) -> Vec<ESNormalTransactionStrings> {
+    from_str(&read_to_string(path).unwrap()).unwrap()
+}
+
+#[test]
+fn pages_end_at_page_size_boundary() {
+    let dir = TempDir::new("pages_end_at_page_size_boundary");
+    let path = dir.0.join("history.json");
+    // two transactions per block, the number of transactions is a multiple of the page size
+    let server = etherscan((0..8).map(|i| transaction(100 + i / 2, i as u64)).collect());
+
+    let added = fetch_account(&mut client(&server), &path, ADDRESS, 100, 1000, 4).unwrap();
+
+    assert_eq!(added, 8);
+    // every full page is followed by a request starting at its last block, which is fetched
+    // again, until a page is not full
+    assert_eq!(start_blocks(&server), vec![100, 101, 102, 103]);
+    let hashes: Vec<String> = stored(&path).into_iter().map(|t| t.hash).collect();
+    assert_eq!(
+        hashes,
+        (0..8).map(|i| format!("0x{:064x}", i)).collect::<Vec<_>>()
+    );
+}
+
+#[test]
+fn block_larger_than_page_is_an_error() {
+    let dir = TempDir::new("block_larger_than_page_is_an_error");
+    let path = dir.0.join("history.json");
+    let server = etherscan((0..3).map(|i| transaction(100, i)).collect());
+
+    assert!(fetch_account(&mut client(&server), &path, ADDRESS, 100, 1000, 2).is_err());
+}
+
+#[test]
+fn resumes_from_highest_stored_block() {
+    let dir = TempDir::new("resumes_from_highest_stored_block");
+    let path = dir.0.join("history.json");
+    let transactions: Vec<Value> = (0..6).map(|i| transaction(100 + i, i as u64)).collect();
+    write(&path, to_string(&transactions[..3]).unwrap()).unwrap();
+    let server = etherscan(transactions);
+
+    // the start block only applies to files without stored transactions
+    let added = fetch_account(&mut client(&server), &path, ADDRESS, 0, 1000, 10).unwrap();
+
+    assert_eq!(added, 3);
+    assert_eq!(start_blocks(&server), vec![102]);
+    assert_eq!(stored(&path).len(), 6);
+}
+
+#[test]
+fn retries_when_rate_limited() {
+    let dir = TempDir::new("retries_when_rate_limited");
+    let path = dir.0.join("history.json");
+    let limited = json!({"status": "0", "message": "NOTOK", "result": "Max rate limit reached"});
+    let page = json!({"status": "1", "message": "OK", "result": [transaction(100, 0)]});
+    let server = MockServer::start(move |_, n| if n < 2 { &limited } else { &page }.to_string());
+
+    let added = fetch_account(&mut client(&server), &path, ADDRESS, 100, 1000, 10).unwrap();
+
+    assert_eq!(added, 1);
+    assert_eq!(server.requests().len(), 3);
+}
+
+#[test]
+fn gives_up_after_repeated_rate_limits() {
+    let dir = TempDir::new("gives_up_after_repeated_rate_limits");
+    let path = dir.0.join("history.json");
+    let server = MockServer::start(|_, _| {
+        json!({"status": "0", "message": "NOTOK", "result": "Max rate limit reached"}).to_string()
+    });
+
+    let e = fetch_account(&mut client(&server), &path, ADDRESS, 100, 1000, 10).unwrap_err();
+
+    assert!(e.to_string().contains("rate limit"));
+    // the first request and five retries
+    assert_eq!(server.requests().len(), 6);
+}
+
+#[test]
+fn other_api_errors_are_not_retried() {
+    let dir = TempDir::new("other_api_errors_are_not_retried");
+    let path = dir.0.join("history.json");
+    let server = MockServer::start(|_, _| {
+        json!({"status": "0", "message": "NOTOK", "result": "Invalid API Key"}).to_string()
+    });
+
+    assert!(fetch_account(&mut client(&server), &path, ADDRESS, 100, 1000, 10).is_err());
+    assert_eq!(server.requests().len(), 1);
+}
+
+#[test]
+fn writes_through_temporary_file() {
+    let dir = TempDir::new("writes_through_temporary_file");
+    let path = dir.0.join("history.json");
+    let tmp = dir.0.join("history.json.tmp");
+    // a leftover of an interrupted run
+    write(&tmp, "[{").unwrap();
+    let server = etherscan((0..3).map(|i| transaction(100 + i, i as u64)).collect());
+
+    fetch_account(&mut client(&server), &path, ADDRESS, 100, 1000, 10).unwrap();
+
+    assert_eq!(stored(&path).len(), 3);
+    assert!(!tmp.exists());
+}
+
+#[test]
+fn keeps_fetched_pages_when_fetching_fails() {
+    let dir = TempDir::new("keeps_fetched_pages_when_fetching_fails");
+    let path = dir.0.join("history.json");
+    let page = json!({"status": "1", "message": "OK", "result": [transaction(100, 0), transaction(101, 1)]});
+    let server = MockServer::start(move |_, n| match n {
+        0 => page.to_string(),
+        _ => json!({"status": "0", "message": "NOTOK", "result": "Invalid API Key"}).to_string(),
+    });
+
+    assert!(fetch_account(&mut client(&server), &path, ADDRESS, 100, 1000, 2).is_err());
+    // the stored file is complete, so the next run resumes after the first page
+    assert_eq!(stored(&path).len(), 2);
+    assert!(!dir.0.join("history.json.tmp").exists());
+}
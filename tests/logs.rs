@@ -0,0 +1,143 @@
+mod common;
+
+use common::{MockServer, TempDir};
+use ethabi::Uint;
+use serde_json::{from_str, json, Value};
+use std::fs::read_to_string;
+use tornado_cash_heuristics::fetch::{fetch_logs, RpcClient};
+use tornado_cash_heuristics::{load, load_log_files, split_deposit_withdraw_logs, PoolLog};
+use web3::types::{H160, H256};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/logs.json");
+
+fn hash(byte: u8) -> H256 {
+    H256::repeat_byte(byte)
+}
+
+fn address(byte: u8) -> H160 {
+    H160::repeat_byte(byte)
+}
+
+#[test]
+fn decodes_deposit_and_withdrawal_logs() {
+    let (logs, errors) = load_log_files(vec![(1, FIXTURE)]).unwrap();
+    assert!(errors.is_empty());
+
+    let (deposits, withdraws, errors) =
+        split_deposit_withdraw_logs(&logs.iter().collect::<Vec<&PoolLog>>());
+
+    // both deposits of the transaction are kept, the deposit with unknown sender is dropped
+    assert_eq!(deposits.len(), 2);
+    for d in &deposits {
+        assert_eq!(d.chain, 1);
+        assert_eq!(d.transaction_hash, hash(0xaa));
+        assert_eq!(d.block_number, 9200000);
+        assert_eq!(d.pool.to_string(), "0.1 ETH");
+        assert_eq!(d.from, address(0x11));
+        assert_eq!(d.timestamp, Some(1600000000));
+        assert_eq!(d.gas_price, None);
+    }
+
+    // the removed withdrawal is skipped
+    assert_eq!(withdraws.len(), 1);
+    let w = &withdraws[0];
+    assert_eq!(w.transaction_hash, hash(0xbb));
+    assert_eq!(w.block_number, 9200100);
+    assert_eq!(w.pool.to_string(), "0.1 ETH");
+    assert_eq!(w.receiver, address(0x22));
+    assert_eq!(w.relayer, address(0x33));
+    assert_eq!(w.fee, Uint::exp10(16));
+    assert_eq!(w.timestamp, None);
+
+    // the truncated withdrawal cannot be decoded
+    assert_eq!(errors.len(), 1);
+    assert!(errors[0].to_string().contains(&format!("{:?}", hash(0xee))));
+}
+
+#[test]
+fn loads_logs_without_calls() {
+    let data = load(&[], &[(1, FIXTURE)], false).unwrap();
+
+    assert_eq!(data.deposits.len(), 2);
+    assert_eq!(data.withdraws.len(), 1);
+    assert_eq!(data.skipped.len(), 1);
+    assert!(load(&[], &[(1, FIXTURE)], true).is_err());
+}
+
+// answers like a JSON-RPC endpoint of mainnet that returns the logs of the fixture (without the
+// fields added by fetch_logs) and the sender 0x99..99 for every transaction
+fn endpoint() -> MockServer {
+    let logs: Vec<Value> = from_str::<Vec<Value>>(&read_to_string(FIXTURE).unwrap())
+        .unwrap()
+        .into_iter()
+        .map(|mut l| {
+            let fields = l.as_object_mut().unwrap();
+            fields.remove("from");
+            fields.remove("chain");
+            l
+        })
+        .collect();
+
+    MockServer::start(move |request, _| {
+        let request: Value = from_str(&request.body).unwrap();
+        let result = match request["method"].as_str().unwrap() {
+            "eth_chainId" => json!("0x1"),
+            "eth_getLogs" => json!(logs),
+            "eth_getTransactionByHash" => json!({
+                "hash": request["params"][0],
+                "nonce": "0x0",
+                "blockHash": null,
+                "blockNumber": null,
+                "transactionIndex": null,
+                "from": format!("{:?}", address(0x99)),
+                "to": null,
+                "value": "0x0",
+                "gasPrice": "0x0",
+                "gas": "0x0",
+                "input": "0x"
+            }),
+            m => panic!("unexpected method {}", m),
+        };
+
+        json!({"jsonrpc": "2.0", "id": request["id"], "result": result}).to_string()
+    })
+}
+
+#[test]
+fn fetches_senders_of_deposits() {
+    let dir = TempDir::new("fetches_senders_of_deposits");
+    let path = dir.0.join("logs.json");
+    let server = endpoint();
+    let mut client = RpcClient::new(&server.url).unwrap();
+
+    let added = fetch_logs(&mut client, &path, &[address(0x12)], 9200000, 9200200, 1000).unwrap();
+    assert_eq!(added, 6);
+
+    // the sender is looked up once per transaction with Deposit events
+    let lookups: Vec<Value> = server
+        .requests()
+        .iter()
+        .map(|r| from_str::<Value>(&r.body).unwrap())
+        .filter(|r| r["method"] == "eth_getTransactionByHash")
+        .map(|r| r["params"][0].clone())
+        .collect();
+    assert_eq!(
+        lookups,
+        vec![
+            json!(format!("{:?}", hash(0xaa))),
+            json!(format!("{:?}", hash(0xcc)))
+        ]
+    );
+
+    // the sender is stored with the Deposit logs only, the chain with every log
+    let stored: Vec<PoolLog> = from_str(&read_to_string(&path).unwrap()).unwrap();
+    let senders: Vec<Option<H160>> = stored.iter().map(|l| l.from).collect();
+    let sender = Some(address(0x99));
+    assert_eq!(senders, vec![sender, sender, sender, None, None, None]);
+    assert!(stored.iter().all(|l| l.chain == 1));
+
+    // so that all deposits can be decoded from the file
+    let data = load(&[], &[(1, path.to_str().unwrap())], false).unwrap();
+    assert_eq!(data.deposits.len(), 3);
+    assert!(data.deposits.iter().all(|d| d.from == address(0x99)));
+}